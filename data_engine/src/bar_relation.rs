@@ -0,0 +1,56 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::week_day_data::PeriodAgg;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BarRelation {
+    Inside,
+    Outside,
+    Up,
+    Down,
+}
+
+impl BarRelation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BarRelation::Inside => "Inside",
+            BarRelation::Outside => "Outside",
+            BarRelation::Up => "Up",
+            BarRelation::Down => "Down",
+        }
+    }
+
+    /// Relation of the current bar's range to the previous bar's range.
+    /// Inside: contained by the prior range. Outside: takes out both ends.
+    /// Otherwise Up when it makes a higher high, Down when it doesn't.
+    pub fn from_ranges(prev_high: f64, prev_low: f64, cur_high: f64, cur_low: f64) -> Self {
+        if cur_high <= prev_high && cur_low >= prev_low {
+            BarRelation::Inside
+        } else if cur_high > prev_high && cur_low < prev_low {
+            BarRelation::Outside
+        } else if cur_high > prev_high {
+            BarRelation::Up
+        } else {
+            BarRelation::Down
+        }
+    }
+}
+
+impl fmt::Display for BarRelation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Relation of day `cur` to the day before it, `prev`; see `BarRelation::from_ranges`.
+pub fn bar_relationship(prev: &PeriodAgg, cur: &PeriodAgg) -> BarRelation {
+    BarRelation::from_ranges(prev.high, prev.low, cur.high, cur.low)
+}
+
+/// `bar_relationship` of each consecutive pair of days. `aggs` must be in date order;
+/// `result[i]` describes `aggs[i + 1]`, so the first day has no entry.
+pub fn bar_relationships(aggs: &[PeriodAgg]) -> Vec<BarRelation> {
+    aggs.windows(2).map(|pair| bar_relationship(&pair[0], &pair[1])).collect()
+}
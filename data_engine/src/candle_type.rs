@@ -6,6 +6,112 @@ pub const DEFAULT_BODY_WICK_RATIO_SHORT: f64 = 0.3;
 pub const DEFAULT_UPPER_VS_LOWER_RATIO: f64 = 0.6;
 pub const DEFAULT_EPS: f64 = 1e-9;
 
+/// Which price a candle's body is measured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BodyReference {
+    /// The candle's own open (the classic definition).
+    #[default]
+    OwnOpen,
+    /// The prior candle's close, so an opening gap counts as part of the body.
+    PrevClose,
+}
+
+/// Classifier thresholds bundled together, defaulting to the `DEFAULT_*` constants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CandleConfig {
+    pub doji_body_ratio: f64,
+    pub body_wick_ratio_long: f64,
+    pub body_wick_ratio_short: f64,
+    pub upper_vs_lower_ratio: f64,
+    pub eps: f64,
+    pub body_reference: BodyReference,
+    /// Below this `pattern_confidence` a candle is labelled `Uncertain`; 0.0 never does.
+    pub min_confidence: f64,
+    /// Ratios for bearish candles (`close < open`). `None` (the default) uses the
+    /// ratios above for both directions.
+    pub bearish: Option<PatternThresholds>,
+}
+
+/// The direction-sensitive subset of `CandleConfig`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatternThresholds {
+    pub doji_body_ratio: f64,
+    pub body_wick_ratio_long: f64,
+    pub body_wick_ratio_short: f64,
+    pub upper_vs_lower_ratio: f64,
+}
+
+impl Default for CandleConfig {
+    fn default() -> Self {
+        CandleConfig::from(PatternParams::default())
+    }
+}
+
+/// The five classifier ratios plus `eps`, for `pattern_from_ohlc_with`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatternParams {
+    pub doji_body_ratio: f64,
+    pub body_wick_ratio_long: f64,
+    pub body_wick_ratio_short: f64,
+    pub upper_vs_lower_ratio: f64,
+    pub eps: f64,
+}
+
+impl Default for PatternParams {
+    fn default() -> Self {
+        PatternParams {
+            doji_body_ratio: DEFAULT_DOJI_BODY_RATIO,
+            body_wick_ratio_long: DEFAULT_BODY_WICK_RATIO_LONG,
+            body_wick_ratio_short: DEFAULT_BODY_WICK_RATIO_SHORT,
+            upper_vs_lower_ratio: DEFAULT_UPPER_VS_LOWER_RATIO,
+            eps: DEFAULT_EPS,
+        }
+    }
+}
+
+/// A `CandleConfig` with `params` as its ratios and everything else at the default.
+impl From<PatternParams> for CandleConfig {
+    fn from(params: PatternParams) -> Self {
+        CandleConfig {
+            doji_body_ratio: params.doji_body_ratio,
+            body_wick_ratio_long: params.body_wick_ratio_long,
+            body_wick_ratio_short: params.body_wick_ratio_short,
+            upper_vs_lower_ratio: params.upper_vs_lower_ratio,
+            eps: params.eps,
+            body_reference: BodyReference::OwnOpen,
+            min_confidence: 0.0,
+            bearish: None,
+        }
+    }
+}
+
+impl CandleConfig {
+    /// The ratios and `eps` of this config.
+    pub fn params(&self) -> PatternParams {
+        PatternParams {
+            doji_body_ratio: self.doji_body_ratio,
+            body_wick_ratio_long: self.body_wick_ratio_long,
+            body_wick_ratio_short: self.body_wick_ratio_short,
+            upper_vs_lower_ratio: self.upper_vs_lower_ratio,
+            eps: self.eps,
+        }
+    }
+
+    /// This config with the bearish ratios swapped in when the candle is bearish.
+    pub fn for_candle(&self, open: f64, close: f64) -> CandleConfig {
+        match self.bearish {
+            Some(t) if close < open => CandleConfig {
+                doji_body_ratio: t.doji_body_ratio,
+                body_wick_ratio_long: t.body_wick_ratio_long,
+                body_wick_ratio_short: t.body_wick_ratio_short,
+                upper_vs_lower_ratio: t.upper_vs_lower_ratio,
+                ..*self
+            },
+            _ => *self,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CandlePattern {
     BullishHammer,
@@ -16,7 +122,21 @@ pub enum CandlePattern {
     BearishLongBody,
     MildBullish,
     MildBearish,
+    /// Small body the doji subtypes below don't cover.
     DojiSpinningTop,
+    /// Doji with a long lower wick and next to no upper wick.
+    DragonflyDoji,
+    /// Doji with a long upper wick and next to no lower wick.
+    GravestoneDoji,
+    /// Doji with long wicks of similar length on both sides.
+    LongLeggedDoji,
+    /// Doji with wicks on both sides, one clearly longer.
+    Doji,
+    BullishEngulfing,
+    BearishEngulfing,
+    BullishHarami,
+    BearishHarami,
+    Uncertain,
     Unknown,
 }
 
@@ -32,9 +152,69 @@ impl CandlePattern {
             CandlePattern::MildBullish => "Mild Bullish",
             CandlePattern::MildBearish => "Mild Bearish",
             CandlePattern::DojiSpinningTop => "Doji/SpinningTop",
+            CandlePattern::DragonflyDoji => "Dragonfly Doji",
+            CandlePattern::GravestoneDoji => "Gravestone Doji",
+            CandlePattern::LongLeggedDoji => "Long-Legged Doji",
+            CandlePattern::Doji => "Doji",
+            CandlePattern::BullishEngulfing => "Bullish Engulfing",
+            CandlePattern::BearishEngulfing => "Bearish Engulfing",
+            CandlePattern::BullishHarami => "Bullish Harami",
+            CandlePattern::BearishHarami => "Bearish Harami",
+            CandlePattern::Uncertain => "Uncertain",
             CandlePattern::Unknown => "Unknown",
         }
     }
+
+    /// Two/three-letter code for compact sequences: a leading `B` is bullish, `R` bearish.
+    pub fn short_code(&self) -> &'static str {
+        match self {
+            CandlePattern::BullishHammer => "BH",
+            CandlePattern::BearishHammer => "RH",
+            CandlePattern::BullishShootingStar => "BSS",
+            CandlePattern::BearishShootingStar => "RSS",
+            CandlePattern::BullishLongBody => "BLB",
+            CandlePattern::BearishLongBody => "RLB",
+            CandlePattern::MildBullish => "MB",
+            CandlePattern::MildBearish => "MR",
+            CandlePattern::DojiSpinningTop => "DST",
+            CandlePattern::DragonflyDoji => "DFD",
+            CandlePattern::GravestoneDoji => "GSD",
+            CandlePattern::LongLeggedDoji => "LLD",
+            CandlePattern::Doji => "DJ",
+            CandlePattern::BullishEngulfing => "BE",
+            CandlePattern::BearishEngulfing => "RE",
+            CandlePattern::BullishHarami => "BHR",
+            CandlePattern::BearishHarami => "RHR",
+            CandlePattern::Uncertain => "UN",
+            CandlePattern::Unknown => "UK",
+        }
+    }
+
+    /// Inverse of `as_str`.
+    pub fn from_label(label: &str) -> Option<Self> {
+        Some(match label {
+            "Bullish Hammer" => CandlePattern::BullishHammer,
+            "Bearish Hammer" => CandlePattern::BearishHammer,
+            "Bullish Shooting Star" => CandlePattern::BullishShootingStar,
+            "Bearish Shooting Star" => CandlePattern::BearishShootingStar,
+            "Bullish Long Body" => CandlePattern::BullishLongBody,
+            "Bearish Long Body" => CandlePattern::BearishLongBody,
+            "Mild Bullish" => CandlePattern::MildBullish,
+            "Mild Bearish" => CandlePattern::MildBearish,
+            "Doji/SpinningTop" => CandlePattern::DojiSpinningTop,
+            "Dragonfly Doji" => CandlePattern::DragonflyDoji,
+            "Gravestone Doji" => CandlePattern::GravestoneDoji,
+            "Long-Legged Doji" => CandlePattern::LongLeggedDoji,
+            "Doji" => CandlePattern::Doji,
+            "Bullish Engulfing" => CandlePattern::BullishEngulfing,
+            "Bearish Engulfing" => CandlePattern::BearishEngulfing,
+            "Bullish Harami" => CandlePattern::BullishHarami,
+            "Bearish Harami" => CandlePattern::BearishHarami,
+            "Uncertain" => CandlePattern::Uncertain,
+            "Unknown" => CandlePattern::Unknown,
+            _ => return None,
+        })
+    }
 }
 
 impl fmt::Display for CandlePattern {
@@ -43,6 +223,11 @@ impl fmt::Display for CandlePattern {
     }
 }
 
+/// Classify a single candle from its OHLC.
+///
+/// Only price differences are used, so negative prices (e.g. calendar spreads)
+/// classify exactly like the same candle shifted above zero.
+#[allow(clippy::too_many_arguments)]
 pub fn pattern_from_ohlc(
     open: f64,
     high: f64,
@@ -54,25 +239,67 @@ pub fn pattern_from_ohlc(
     upper_vs_lower_ratio: f64,
     eps: f64,
 ) -> String {
-    let full_range = high - low;
-    let body_range = (close - open).abs();
+    classify_ohlc(
+        open, high, low, close,
+        doji_body_ratio, body_wick_ratio_long,
+        body_wick_ratio_short, upper_vs_lower_ratio, eps,
+    ).to_string()
+}
+
+/// `pattern_from_ohlc` with its thresholds taken from `params`.
+pub fn pattern_from_ohlc_with(open: f64, high: f64, low: f64, close: f64, params: &PatternParams) -> String {
+    pattern_from_ohlc(
+        open, high, low, close,
+        params.doji_body_ratio, params.body_wick_ratio_long,
+        params.body_wick_ratio_short, params.upper_vs_lower_ratio, params.eps,
+    )
+}
+
+/// Body and wicks as fractions of the bar's full range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CandleMetrics {
+    pub body_ratio: f64,
+    pub upper_wick_ratio: f64,
+    pub lower_wick_ratio: f64,
+}
 
+/// The ratios the classifier works from. `None` when the range is below `eps`.
+pub fn candle_metrics(open: f64, high: f64, low: f64, close: f64, eps: f64) -> Option<CandleMetrics> {
+    let full_range = high - low;
     if full_range < eps {
-        return CandlePattern::Unknown.to_string();
+        return None;
     }
+    Some(CandleMetrics {
+        body_ratio: (close - open).abs() / full_range,
+        upper_wick_ratio: (high - close.max(open)) / full_range,
+        lower_wick_ratio: (open.min(close) - low) / full_range,
+    })
+}
 
-    let upper_wick = high - close.max(open);
-    let lower_wick = open.min(close) - low;
-
-    let body_ratio = body_range / full_range;
-    let upper_wick_ratio = upper_wick / full_range;
-    let lower_wick_ratio = lower_wick / full_range;
+/// `pattern_from_ohlc` returning the `CandlePattern` itself rather than its label.
+#[allow(clippy::too_many_arguments)]
+pub fn classify_ohlc(
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    doji_body_ratio: f64,
+    body_wick_ratio_long: f64,
+    body_wick_ratio_short: f64,
+    upper_vs_lower_ratio: f64,
+    eps: f64,
+) -> CandlePattern {
+    let CandleMetrics { body_ratio, upper_wick_ratio, lower_wick_ratio } =
+        match candle_metrics(open, high, low, close, eps) {
+            Some(m) => m,
+            None => return CandlePattern::Unknown,
+        };
 
     let is_bullish = close > open;
 
     // Doji or Spinning Top
     if body_ratio <= doji_body_ratio {
-        return CandlePattern::DojiSpinningTop.to_string();
+        return doji_kind(upper_wick_ratio, lower_wick_ratio, doji_body_ratio, upper_vs_lower_ratio);
     }
 
     // Hammer/Shooting Star
@@ -82,29 +309,333 @@ pub fn pattern_from_ohlc(
                 CandlePattern::BullishHammer
             } else {
                 CandlePattern::BearishHammer
-            }.to_string();
+            };
         } else if lower_wick_ratio / (upper_wick_ratio + eps) < upper_vs_lower_ratio {
             return if is_bullish {
                 CandlePattern::BullishShootingStar
             } else {
                 CandlePattern::BearishShootingStar
-            }.to_string();
+            };
         }
     }
 
     // Long Body
     if body_ratio >= body_wick_ratio_long {
         if is_bullish {
-            return CandlePattern::BullishLongBody.to_string();
+            return CandlePattern::BullishLongBody;
         } else {
-            return CandlePattern::BearishLongBody.to_string();
+            return CandlePattern::BearishLongBody;
         }
     }
 
     // Mild Body
     if is_bullish {
-        CandlePattern::MildBullish.to_string()
+        CandlePattern::MildBullish
     } else {
-        CandlePattern::MildBearish.to_string()
+        CandlePattern::MildBearish
     }
-}
\ No newline at end of file
+}
+
+// Doji subtype from its wicks. A wick no larger than `doji_body_ratio` counts as
+// absent; two wicks are of similar length when each is at least
+// `upper_vs_lower_ratio` of the other. Anything else, i.e. both wicks absent (only
+// possible once `doji_body_ratio` reaches 1/3), stays the catch-all `DojiSpinningTop`.
+fn doji_kind(upper_wick_ratio: f64, lower_wick_ratio: f64, doji_body_ratio: f64, upper_vs_lower_ratio: f64) -> CandlePattern {
+    let tiny_upper = upper_wick_ratio <= doji_body_ratio;
+    let tiny_lower = lower_wick_ratio <= doji_body_ratio;
+    match (tiny_upper, tiny_lower) {
+        (true, false) => CandlePattern::DragonflyDoji,
+        (false, true) => CandlePattern::GravestoneDoji,
+        (false, false) => {
+            let shorter = upper_wick_ratio.min(lower_wick_ratio);
+            let longer = upper_wick_ratio.max(lower_wick_ratio);
+            if shorter >= upper_vs_lower_ratio * longer {
+                CandlePattern::LongLeggedDoji
+            } else {
+                CandlePattern::Doji
+            }
+        }
+        _ => CandlePattern::DojiSpinningTop,
+    }
+}
+
+/// How far inside its decision region a candle sits, from 0.0 (on a threshold) to
+/// 1.0 (as far from every threshold as the region allows). `Unknown` candles score 0.0.
+///
+/// The score is the candle's margin to the nearest body-ratio threshold bounding its
+/// pattern, divided by the widest margin possible in that band; hammers and shooting
+/// stars also take the wick-ratio margin into account and keep the smaller of the two.
+pub fn pattern_confidence(open: f64, high: f64, low: f64, close: f64, cfg: &CandleConfig) -> f64 {
+    let cfg = &cfg.for_candle(open, close);
+    let metrics = match candle_metrics(open, high, low, close, cfg.eps) {
+        Some(m) => m,
+        None => return 0.0,
+    };
+    let pattern = classify_ohlc(
+        open, high, low, close,
+        cfg.doji_body_ratio, cfg.body_wick_ratio_long,
+        cfg.body_wick_ratio_short, cfg.upper_vs_lower_ratio, cfg.eps,
+    );
+    confidence_of(pattern, &metrics, cfg)
+}
+
+// `pattern_confidence` for a given `pattern`, whether or not the cascade would pick it.
+fn confidence_of(pattern: CandlePattern, m: &CandleMetrics, cfg: &CandleConfig) -> f64 {
+    let CandleMetrics { body_ratio, upper_wick_ratio, lower_wick_ratio } = *m;
+
+    // Normalized distance of `x` from the nearer edge of `[lo, hi]`.
+    let band_margin = |x: f64, lo: f64, hi: f64| {
+        if hi > lo { (x - lo).min(hi - x) / ((hi - lo) / 2.0) } else { 0.0 }
+    };
+    let wick_margin = |ratio: f64| {
+        if cfg.upper_vs_lower_ratio > 0.0 { 1.0 - ratio / cfg.upper_vs_lower_ratio } else { 0.0 }
+    };
+
+    let confidence = match pattern {
+        // Two-candle patterns never come out of the single-candle classifier.
+        CandlePattern::Unknown
+        | CandlePattern::BullishEngulfing | CandlePattern::BearishEngulfing
+        | CandlePattern::BullishHarami | CandlePattern::BearishHarami => 0.0,
+        CandlePattern::DojiSpinningTop | CandlePattern::DragonflyDoji | CandlePattern::GravestoneDoji
+        | CandlePattern::LongLeggedDoji | CandlePattern::Doji => {
+            if cfg.doji_body_ratio > 0.0 { 1.0 - body_ratio / cfg.doji_body_ratio } else { 0.0 }
+        }
+        CandlePattern::BullishHammer | CandlePattern::BearishHammer => {
+            band_margin(body_ratio, cfg.doji_body_ratio, cfg.body_wick_ratio_short)
+                .min(wick_margin(upper_wick_ratio / (lower_wick_ratio + cfg.eps)))
+        }
+        CandlePattern::BullishShootingStar | CandlePattern::BearishShootingStar => {
+            band_margin(body_ratio, cfg.doji_body_ratio, cfg.body_wick_ratio_short)
+                .min(wick_margin(lower_wick_ratio / (upper_wick_ratio + cfg.eps)))
+        }
+        CandlePattern::BullishLongBody | CandlePattern::BearishLongBody => {
+            if cfg.body_wick_ratio_long < 1.0 {
+                (body_ratio - cfg.body_wick_ratio_long) / (1.0 - cfg.body_wick_ratio_long)
+            } else {
+                0.0
+            }
+        }
+        CandlePattern::MildBullish | CandlePattern::MildBearish
+        | CandlePattern::Uncertain => {
+            band_margin(body_ratio, cfg.doji_body_ratio, cfg.body_wick_ratio_long)
+        }
+    };
+    confidence.clamp(0.0, 1.0)
+}
+
+/// Every single-candle pattern whose criteria the candle meets, each tested on its
+/// own rather than first-match-wins, highest `pattern_confidence` first. Empty for
+/// zero-range candles.
+pub fn all_patterns_from_ohlc(open: f64, high: f64, low: f64, close: f64, cfg: &CandleConfig) -> Vec<CandlePattern> {
+    let cfg = &cfg.for_candle(open, close);
+    let m = match candle_metrics(open, high, low, close, cfg.eps) {
+        Some(m) => m,
+        None => return Vec::new(),
+    };
+    let bullish = close > open;
+    let pick = |bull: CandlePattern, bear: CandlePattern| if bullish { bull } else { bear };
+
+    let mut matches = Vec::new();
+    if m.body_ratio <= cfg.doji_body_ratio {
+        matches.push(doji_kind(m.upper_wick_ratio, m.lower_wick_ratio, cfg.doji_body_ratio, cfg.upper_vs_lower_ratio));
+    }
+    if m.body_ratio < cfg.body_wick_ratio_short {
+        if m.upper_wick_ratio / (m.lower_wick_ratio + cfg.eps) < cfg.upper_vs_lower_ratio {
+            matches.push(pick(CandlePattern::BullishHammer, CandlePattern::BearishHammer));
+        }
+        if m.lower_wick_ratio / (m.upper_wick_ratio + cfg.eps) < cfg.upper_vs_lower_ratio {
+            matches.push(pick(CandlePattern::BullishShootingStar, CandlePattern::BearishShootingStar));
+        }
+    }
+    if m.body_ratio >= cfg.body_wick_ratio_long {
+        matches.push(pick(CandlePattern::BullishLongBody, CandlePattern::BearishLongBody));
+    } else if m.body_ratio > cfg.doji_body_ratio {
+        matches.push(pick(CandlePattern::MildBullish, CandlePattern::MildBearish));
+    }
+
+    let mut scored: Vec<(CandlePattern, f64)> = matches.into_iter()
+        .map(|p| (p, confidence_of(p, &m, cfg)))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.into_iter().map(|(p, _)| p).collect()
+}
+
+/// `pattern_from_ohlc` driven by a `CandleConfig`. Candles whose
+/// `pattern_confidence` falls below `cfg.min_confidence` are labelled `Uncertain`.
+pub fn pattern_from_ohlc_cfg(open: f64, high: f64, low: f64, close: f64, cfg: &CandleConfig) -> String {
+    let cfg = &cfg.for_candle(open, close);
+    if cfg.min_confidence > 0.0 && pattern_confidence(open, high, low, close, cfg) < cfg.min_confidence {
+        return CandlePattern::Uncertain.to_string();
+    }
+    pattern_from_ohlc_with(open, high, low, close, &cfg.params())
+}
+
+/// Classify a candle with an optional gap-adjusted reference.
+///
+/// With `BodyReference::OwnOpen` this is `pattern_from_ohlc` on the candle as-is and
+/// `prev_close` is ignored. With `BodyReference::PrevClose` the body is measured from
+/// the prior close to this close, and the range is widened to include the prior close
+/// (the true range), so a gap-and-go day reads as the strong move it was.
+pub fn pattern_from_ohlc_ref(
+    prev_close: f64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    cfg: &CandleConfig,
+) -> String {
+    let (open, high, low) = match cfg.body_reference {
+        BodyReference::OwnOpen => (open, high, low),
+        BodyReference::PrevClose => (prev_close, high.max(prev_close), low.min(prev_close)),
+    };
+    pattern_from_ohlc_cfg(open, high, low, close, cfg)
+}
+
+/// Two-candle reversal pattern from consecutive `(open, high, low, close)` tuples.
+///
+/// Engulfing: the current body reverses the previous one and covers it. Harami: the
+/// current body reverses the previous one and sits inside it. Bodies smaller than
+/// `eps` never qualify; anything else is `Unknown`.
+pub fn pattern_from_pair(prev: (f64, f64, f64, f64), cur: (f64, f64, f64, f64), eps: f64) -> String {
+    let (prev_open, _, _, prev_close) = prev;
+    let (cur_open, _, _, cur_close) = cur;
+    let prev_body = prev_close - prev_open;
+    let cur_body = cur_close - cur_open;
+
+    if prev_body.abs() < eps || cur_body.abs() < eps || prev_body.signum() == cur_body.signum() {
+        return CandlePattern::Unknown.to_string();
+    }
+
+    let (prev_bottom, prev_top) = (prev_open.min(prev_close), prev_open.max(prev_close));
+    let (cur_bottom, cur_top) = (cur_open.min(cur_close), cur_open.max(cur_close));
+    let bullish = cur_body > 0.0;
+
+    let pattern = if cur_bottom <= prev_bottom && cur_top >= prev_top && cur_body.abs() > prev_body.abs() {
+        if bullish { CandlePattern::BullishEngulfing } else { CandlePattern::BearishEngulfing }
+    } else if cur_bottom >= prev_bottom && cur_top <= prev_top && cur_body.abs() < prev_body.abs() {
+        if bullish { CandlePattern::BullishHarami } else { CandlePattern::BearishHarami }
+    } else {
+        CandlePattern::Unknown
+    };
+    pattern.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearish_thresholds_apply_only_to_bearish_candles() {
+        let strict_bears = CandleConfig {
+            bearish: Some(PatternThresholds {
+                doji_body_ratio: DEFAULT_DOJI_BODY_RATIO,
+                body_wick_ratio_long: 0.7,
+                body_wick_ratio_short: DEFAULT_BODY_WICK_RATIO_SHORT,
+                upper_vs_lower_ratio: DEFAULT_UPPER_VS_LOWER_RATIO,
+            }),
+            ..CandleConfig::default()
+        };
+
+        // Mirror-image candles: 60% body, the rest a single wick on the opening side.
+        let bull = (100.0, 110.0, 100.0, 106.0);
+        let bear = (106.0, 106.0, 96.0, 100.0);
+
+        assert_eq!(
+            pattern_from_ohlc_cfg(bull.0, bull.1, bull.2, bull.3, &strict_bears),
+            CandlePattern::BullishLongBody.to_string()
+        );
+        assert_eq!(
+            pattern_from_ohlc_cfg(bear.0, bear.1, bear.2, bear.3, &strict_bears),
+            CandlePattern::MildBearish.to_string()
+        );
+        assert_eq!(
+            pattern_from_ohlc_cfg(bear.0, bear.1, bear.2, bear.3, &CandleConfig::default()),
+            CandlePattern::BearishLongBody.to_string()
+        );
+    }
+
+    #[test]
+    fn prev_close_reference_classifies_gap_day_as_long_body() {
+        // Gapped up from 100 to 110, then drifted in a narrow range.
+        let (prev_close, open, high, low, close) = (100.0, 110.0, 112.0, 109.0, 110.6);
+
+        let own = CandleConfig::default();
+        let gap = CandleConfig { body_reference: BodyReference::PrevClose, ..CandleConfig::default() };
+
+        assert_eq!(
+            pattern_from_ohlc_ref(prev_close, open, high, low, close, &own),
+            CandlePattern::MildBullish.to_string()
+        );
+        assert_eq!(
+            pattern_from_ohlc_ref(prev_close, open, high, low, close, &gap),
+            CandlePattern::BullishLongBody.to_string()
+        );
+    }
+
+    fn default_pattern(open: f64, high: f64, low: f64, close: f64) -> String {
+        pattern_from_ohlc(
+            open, high, low, close,
+            DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG,
+            DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS,
+        )
+    }
+
+    #[test]
+    fn negative_priced_spread_classifies_like_shifted_series() {
+        let spread = [
+            (-2.0, -0.5, -2.1, -0.6),
+            (-0.6, -0.4, -1.8, -1.7),
+            (-1.7, -1.2, -1.75, -1.71),
+            (-1.9, -1.7, -2.5, -1.75),
+        ];
+        let expected = [
+            CandlePattern::BullishLongBody,
+            CandlePattern::BearishLongBody,
+            CandlePattern::GravestoneDoji,
+            CandlePattern::BullishHammer,
+        ];
+
+        for ((open, high, low, close), pattern) in spread.iter().zip(expected.iter()) {
+            assert_eq!(default_pattern(*open, *high, *low, *close), pattern.to_string());
+            assert_eq!(
+                default_pattern(*open, *high, *low, *close),
+                default_pattern(open + 10.0, high + 10.0, low + 10.0, close + 10.0)
+            );
+        }
+    }
+
+    #[test]
+    fn min_confidence_marks_borderline_candles_uncertain() {
+        let strict = CandleConfig { min_confidence: 0.5, ..CandleConfig::default() };
+
+        // Body ratio 0.52, just over the long-body threshold.
+        let (open, high, low, close) = (100.0, 105.0, 99.0, 103.12);
+        assert_eq!(pattern_from_ohlc_cfg(open, high, low, close, &CandleConfig::default()), "Bullish Long Body");
+        assert_eq!(pattern_from_ohlc_cfg(open, high, low, close, &strict), "Uncertain");
+
+        // A near-marubozu is confidently long regardless.
+        assert_eq!(pattern_from_ohlc_cfg(100.0, 110.0, 99.9, 109.9, &strict), "Bullish Long Body");
+    }
+
+    #[test]
+    fn own_open_reference_matches_pattern_from_ohlc() {
+        let cfg = CandleConfig::default();
+        assert_eq!(
+            pattern_from_ohlc_ref(90.0, 100.0, 105.0, 99.5, 104.0, &cfg),
+            default_pattern(100.0, 105.0, 99.5, 104.0)
+        );
+    }
+
+    #[test]
+    fn doji_subtypes_follow_wick_shape() {
+        let kind = |open, high, low, close| default_pattern(open, high, low, close);
+        assert_eq!(kind(100.0, 100.2, 90.0, 100.1), "Dragonfly Doji");
+        assert_eq!(kind(90.1, 100.0, 89.8, 90.0), "Gravestone Doji");
+        assert_eq!(kind(95.0, 100.0, 90.0, 95.2), "Long-Legged Doji");
+        assert_eq!(kind(97.0, 100.0, 90.0, 97.3), "Doji");
+
+        // Body and both wicks a third of the range: every part is "tiny" under a 0.4 doji ratio.
+        let wide = PatternParams { doji_body_ratio: 0.4, ..PatternParams::default() };
+        assert_eq!(pattern_from_ohlc_with(1.0, 3.0, 0.0, 2.0, &wide), "Doji/SpinningTop");
+    }
+}
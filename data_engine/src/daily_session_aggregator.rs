@@ -1,13 +1,10 @@
-use std::cmp::Ordering;
 use std::collections::HashMap;
-use chrono::{Datelike, NaiveDate, NaiveDateTime, Weekday, Timelike};
-use std::error::Error;
-use std::path::Path;
+use chrono::{Datelike, NaiveDateTime, Timelike};
 use serde::{Deserialize, Serialize};
 
-use crate::data_engine::{CsvRecord, parse_ts_to_naive};
-use crate::candle_type::{pattern_from_ohlc, CandlePattern, DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG, DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS};
-use crate::session_data_agg::{SessionAgg};
+use crate::data_engine::{serialize_finite, AggregateOptions, CsvRecord, FloatFormat, SortOrder, iso_week_key, parse_ts_to_naive};
+use crate::candle_type::pattern_from_ohlc_cfg;
+use crate::session_data_agg::{max_volume_session_of, SessionAgg};
 use crate::session_type::Session;
 
 
@@ -30,6 +27,18 @@ pub struct DailySessionTableAgg {
     pub ln_high_time: String,
     pub ny_low_time: String, // Combined NY low time
     pub ny_high_time: String, // Combined NY high time
+    pub max_volume_session: String,
+    // Combined NY candle: NYAM open, NYPM close (NYL without a NYPM), and the extremes
+    // of all three. `None` / empty on days without a NY session.
+    #[serde(serialize_with = "serialize_finite")]
+    pub ny_open: Option<f64>,
+    #[serde(serialize_with = "serialize_finite")]
+    pub ny_high: Option<f64>,
+    #[serde(serialize_with = "serialize_finite")]
+    pub ny_low: Option<f64>,
+    #[serde(serialize_with = "serialize_finite")]
+    pub ny_close: Option<f64>,
+    pub ny_candle_pattern: String,
 }
 
 impl CsvRecord for DailySessionTableAgg {
@@ -39,11 +48,17 @@ impl CsvRecord for DailySessionTableAgg {
             "NYAM_CandlePattern", "NYL_CandlePattern", "NYPM_CandlePattern", 
             "DayHighSession", "DayLowSession",
             "AS_LowTime", "AS_HighTime", "LN_LowTime", "LN_HighTime", 
-            "NY_LowTime", "NY_HighTime",
+            "NY_LowTime", "NY_HighTime", "MaxVolumeSession",
+            "NY_Open", "NY_High", "NY_Low", "NY_Close", "NY_CandlePattern",
         ]
     }
 
     fn record(&self) -> Vec<String> {
+        self.record_fmt(FloatFormat::default())
+    }
+
+    fn record_fmt(&self, fmt: FloatFormat) -> Vec<String> {
+        let price = |v: Option<f64>| v.map(|v| fmt.format(v)).unwrap_or_default();
         vec![
             self.date.clone(),
             self.week.clone(),
@@ -62,12 +77,28 @@ impl CsvRecord for DailySessionTableAgg {
             self.ln_high_time.clone(),
             self.ny_low_time.clone(),
             self.ny_high_time.clone(),
+            self.max_volume_session.clone(),
+            price(self.ny_open),
+            price(self.ny_high),
+            price(self.ny_low),
+            price(self.ny_close),
+            self.ny_candle_pattern.clone(),
         ]
     }
 }
 
 pub fn aggregate_daily_session_table(session_aggs: &[SessionAgg]) -> Vec<DailySessionTableAgg> {
-    let mut daily_map: HashMap<String, Vec<&SessionAgg>> = HashMap::new();
+    aggregate_daily_session_table_sorted(session_aggs, SortOrder::Ascending)
+}
+
+pub fn aggregate_daily_session_table_sorted(session_aggs: &[SessionAgg], order: SortOrder) -> Vec<DailySessionTableAgg> {
+    aggregate_daily_session_table_with(session_aggs, &AggregateOptions { sort_order: order, ..AggregateOptions::default() })
+}
+
+/// `aggregate_daily_session_table` with day, session and NY patterns classified by
+/// `opts.candle_config` and the output in `opts.sort_order`.
+pub fn aggregate_daily_session_table_with(session_aggs: &[SessionAgg], opts: &AggregateOptions) -> Vec<DailySessionTableAgg> {
+    let mut daily_map: HashMap<String, (NaiveDateTime, Vec<&SessionAgg>)> = HashMap::new();
 
     for s_agg in session_aggs {
         let ndt = match parse_ts_to_naive(&s_agg.date) {
@@ -76,17 +107,17 @@ pub fn aggregate_daily_session_table(session_aggs: &[SessionAgg]) -> Vec<DailySe
         };
         let date_key = ndt.format("%Y-%m-%d").to_string();
         daily_map.entry(date_key)
-            .or_insert_with(Vec::new)
-            .push(s_agg);
+            .or_insert_with(|| (ndt, Vec::new()))
+            .1.push(s_agg);
     }
 
     let mut result: Vec<DailySessionTableAgg> = Vec::new();
 
-    for (_key, sessions) in daily_map.into_iter() {
+    for (day_ndt, sessions) in daily_map.into_values() {
         if sessions.is_empty() { continue; }
 
         let mut sorted_sessions = sessions;
-        sorted_sessions.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.session.as_str().cmp(b.session.as_str())));
+        sorted_sessions.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.session.cmp(&b.session)));
 
         let mut day_high = f64::MIN;
         let mut day_low = f64::MAX;
@@ -132,10 +163,8 @@ pub fn aggregate_daily_session_table(session_aggs: &[SessionAgg]) -> Vec<DailySe
                     // Format all times to only show the hour
                     parse_ts_to_naive(&session.low_ts).map(|dt| dt.hour().to_string()).unwrap_or_default(),
                     parse_ts_to_naive(&session.high_ts).map(|dt| dt.hour().to_string()).unwrap_or_default(),
-                    pattern_from_ohlc(
-                        session.open, session.high, session.low, session.close,
-                        DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG,
-                        DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS,
+                    pattern_from_ohlc_cfg(
+                        session.open, session.high, session.low, session.close, &opts.candle_config,
                     ),
                 ),
             );
@@ -149,16 +178,22 @@ pub fn aggregate_daily_session_table(session_aggs: &[SessionAgg]) -> Vec<DailySe
         let day_open = first_session.open;
         let day_close = sorted_sessions.last().unwrap().close;
 
-        let day_candle_pattern = pattern_from_ohlc(
-            day_open, day_high, day_low, day_close,
-            DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG,
-            DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS,
+        let day_candle_pattern = pattern_from_ohlc_cfg(
+            day_open, day_high, day_low, day_close, &opts.candle_config,
         );
 
+        // Sessions are in time order, so the first NY session is NYAM when present and
+        // the last is NYPM, else NYL.
+        let ny_sessions: Vec<&SessionAgg> = sorted_sessions.iter().copied()
+            .filter(|s| matches!(s.session, Session::NYAM | Session::NYL | Session::NYPM))
+            .collect();
+        let ny_candle = ny_sessions.first().zip(ny_sessions.last())
+            .map(|(first, last)| (first.open, ny_high, ny_low, last.close));
+
         let day_agg = DailySessionTableAgg {
             date: first_session.date.clone(),
-            week: format!("Week {}", parse_ts_to_naive(&first_session.date).unwrap().iso_week().week()),
-            day: parse_ts_to_naive(&first_session.date).unwrap().weekday().to_string(),
+            week: iso_week_key(&day_ndt),
+            day: day_ndt.weekday().to_string(),
             day_candle_pattern,
             as_candle_pattern: session_data.get(Session::AS.as_str()).map(|t| t.2.clone()).unwrap_or_default(),
             ln_candle_pattern: session_data.get(Session::LN.as_str()).map(|t| t.2.clone()).unwrap_or_default(),
@@ -173,10 +208,37 @@ pub fn aggregate_daily_session_table(session_aggs: &[SessionAgg]) -> Vec<DailySe
             ln_high_time: session_data.get(Session::LN.as_str()).map(|t| t.1.clone()).unwrap_or_default(),
             ny_low_time,
             ny_high_time,
+            max_volume_session: max_volume_session_of(sorted_sessions.iter().copied())
+                .map(|(session, _)| session.as_str().to_string())
+                .unwrap_or_default(),
+            ny_open: ny_candle.map(|c| c.0),
+            ny_high: ny_candle.map(|c| c.1),
+            ny_low: ny_candle.map(|c| c.2),
+            ny_close: ny_candle.map(|c| c.3),
+            ny_candle_pattern: ny_candle
+                .map(|(o, h, l, c)| pattern_from_ohlc_cfg(o, h, l, c, &opts.candle_config))
+                .unwrap_or_default(),
         };
         result.push(day_agg);
     }
 
-    result.sort_by(|a, b| a.date.cmp(&b.date));
+    opts.sort_order.apply(&mut result, |a, b| a.date.cmp(&b.date));
     result
-}
\ No newline at end of file
+}
+
+/// How many days each session made the day's high and the day's low, as
+/// `(high counts, low counts)` keyed by session label. Days without a recorded
+/// session are left out.
+pub fn session_extreme_counts(table: &[DailySessionTableAgg]) -> (HashMap<String, usize>, HashMap<String, usize>) {
+    let mut highs: HashMap<String, usize> = HashMap::new();
+    let mut lows: HashMap<String, usize> = HashMap::new();
+    for day in table {
+        if !day.day_high_session.is_empty() {
+            *highs.entry(day.day_high_session.clone()).or_insert(0) += 1;
+        }
+        if !day.day_low_session.is_empty() {
+            *lows.entry(day.day_low_session.clone()).or_insert(0) += 1;
+        }
+    }
+    (highs, lows)
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::daily_session_aggregator::DailySessionTableAgg;
+use crate::data_engine::{CsvRecord, FloatFormat, parse_ts_to_naive, serialize_finite};
+use crate::week_day_data::PeriodAgg;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailySummary {
+    pub date: String,
+    pub week: String,
+    pub day: String,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    /// `None` when the open is zero; serialized as `null` in JSON.
+    #[serde(serialize_with = "serialize_finite")]
+    pub day_return: Option<f64>,
+    pub day_candle_pattern: String,
+    pub as_candle_pattern: String,
+    pub ln_candle_pattern: String,
+    pub nyam_candle_pattern: String,
+    pub nyl_candle_pattern: String,
+    pub nypm_candle_pattern: String,
+    pub day_high_session: String,
+    pub day_low_session: String,
+    pub as_low_time: String,
+    pub as_high_time: String,
+    pub ln_low_time: String,
+    pub ln_high_time: String,
+    pub ny_low_time: String,
+    pub ny_high_time: String,
+}
+
+impl CsvRecord for DailySummary {
+    fn headers() -> &'static [&'static str] {
+        &[
+            "Date", "Week", "Day", "Open", "High", "Low", "Close", "Volume", "DayReturn",
+            "DayCandlePattern", "AS_CandlePattern", "LN_CandlePattern",
+            "NYAM_CandlePattern", "NYL_CandlePattern", "NYPM_CandlePattern",
+            "DayHighSession", "DayLowSession",
+            "AS_LowTime", "AS_HighTime", "LN_LowTime", "LN_HighTime",
+            "NY_LowTime", "NY_HighTime",
+        ]
+    }
+
+    fn record(&self) -> Vec<String> {
+        self.record_fmt(FloatFormat::default())
+    }
+
+    fn record_fmt(&self, fmt: FloatFormat) -> Vec<String> {
+        vec![
+            self.date.clone(),
+            self.week.clone(),
+            self.day.clone(),
+            fmt.format(self.open),
+            fmt.format(self.high),
+            fmt.format(self.low),
+            fmt.format(self.close),
+            fmt.format(self.volume),
+            self.day_return.map(|r| fmt.format(r)).unwrap_or_default(),
+            self.day_candle_pattern.clone(),
+            self.as_candle_pattern.clone(),
+            self.ln_candle_pattern.clone(),
+            self.nyam_candle_pattern.clone(),
+            self.nyl_candle_pattern.clone(),
+            self.nypm_candle_pattern.clone(),
+            self.day_high_session.clone(),
+            self.day_low_session.clone(),
+            self.as_low_time.clone(),
+            self.as_high_time.clone(),
+            self.ln_low_time.clone(),
+            self.ln_high_time.clone(),
+            self.ny_low_time.clone(),
+            self.ny_high_time.clone(),
+        ]
+    }
+}
+
+// Daily and session tables format dates differently ("2024-01-02" vs "2024.01.02").
+fn date_key(date: &str) -> Option<String> {
+    parse_ts_to_naive(date).map(|dt| dt.format("%Y-%m-%d").to_string())
+}
+
+/// One wide row per day: `daily` joined by date with its daily session table row.
+pub fn build_daily_summary(daily: &[PeriodAgg], session_table: &[DailySessionTableAgg]) -> Vec<DailySummary> {
+    let sessions_by_date: HashMap<String, &DailySessionTableAgg> = session_table.iter()
+        .filter_map(|row| date_key(&row.date).map(|key| (key, row)))
+        .collect();
+
+    daily.iter().map(|day| {
+        let row = date_key(&day.date).and_then(|key| sessions_by_date.get(&key).copied());
+        let field = |f: fn(&DailySessionTableAgg) -> &String| row.map(|r| f(r).clone()).unwrap_or_default();
+        let day_return = (day.open != 0.0).then(|| (day.close - day.open) / day.open.abs());
+
+        DailySummary {
+            date: day.date.clone(),
+            week: field(|r| &r.week),
+            day: field(|r| &r.day),
+            open: day.open,
+            high: day.high,
+            low: day.low,
+            close: day.close,
+            volume: day.volume,
+            day_return,
+            day_candle_pattern: day.pattern.clone(),
+            as_candle_pattern: field(|r| &r.as_candle_pattern),
+            ln_candle_pattern: field(|r| &r.ln_candle_pattern),
+            nyam_candle_pattern: field(|r| &r.nyam_candle_pattern),
+            nyl_candle_pattern: field(|r| &r.nyl_candle_pattern),
+            nypm_candle_pattern: field(|r| &r.nypm_candle_pattern),
+            day_high_session: field(|r| &r.day_high_session),
+            day_low_session: field(|r| &r.day_low_session),
+            as_low_time: field(|r| &r.as_low_time),
+            as_high_time: field(|r| &r.as_high_time),
+            ln_low_time: field(|r| &r.ln_low_time),
+            ln_high_time: field(|r| &r.ln_high_time),
+            ny_low_time: field(|r| &r.ny_low_time),
+            ny_high_time: field(|r| &r.ny_high_time),
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str, open: f64, close: f64) -> PeriodAgg {
+        PeriodAgg {
+            date: date.to_string(), open, high: open.max(close) + 1.0, low: open.min(close) - 1.0,
+            close, volume: 10.0, members: String::new(), pattern: String::new(),
+        }
+    }
+
+    #[test]
+    fn missing_and_nan_day_return_serialize_as_null() {
+        let mut rows = build_daily_summary(&[day("2024-01-02", 0.0, 5.0), day("2024-01-03", 5.0, 6.0)], &[]);
+        assert_eq!(rows[0].day_return, None);
+
+        let json = serde_json::to_value(&rows).unwrap();
+        assert!(json[0]["day_return"].is_null());
+        assert!(json[1]["day_return"].is_number());
+
+        rows[1].day_return = Some(f64::NAN);
+        let json = serde_json::to_value(&rows).unwrap();
+        assert!(json[1]["day_return"].is_null());
+    }
+}
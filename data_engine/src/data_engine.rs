@@ -1,16 +1,204 @@
-use chrono::{NaiveDate, NaiveDateTime};
-use csv::{ReaderBuilder, WriterBuilder, Trim};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder, Trim};
 use serde::{Deserialize, Serialize};
+
+use crate::candle_type::CandleConfig;
+use crate::session_type::{Session, SessionConfig};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+// Candidate delimiters, in tie-breaking order, and how many lines to sniff them on.
+const DELIMITERS: [u8; 4] = [b'\t', b';', b'|', b','];
+const SNIFF_LINES: usize = 10;
+
 pub trait CsvRecord: serde::Serialize + std::fmt::Debug {
     fn headers() -> &'static [&'static str];
     fn record(&self) -> Vec<String>;
+
+    /// `record()` with floats written in `fmt`. Types with numeric columns override
+    /// this and have `record()` call it with `FloatFormat::default()`.
+    fn record_fmt(&self, _fmt: FloatFormat) -> Vec<String> {
+        self.record()
+    }
+
+    /// `record()` with floats written to `decimals` places.
+    fn record_with_precision(&self, decimals: usize) -> Vec<String> {
+        self.record_fmt(FloatFormat::Fixed(decimals))
+    }
+}
+
+/// How `CsvRecord` writes floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// A fixed number of decimals, `{:.N}`. Tiny prices can round to zero.
+    Fixed(usize),
+    /// Scientific notation, `{:e}`, e.g. `1.2345e-7`. Lossless.
+    Scientific,
+    /// Rust's default `{}`: the shortest digits that read back as the same value.
+    Shortest,
+}
+
+impl Default for FloatFormat {
+    fn default() -> Self {
+        FloatFormat::Fixed(6)
+    }
+}
+
+impl FloatFormat {
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            FloatFormat::Fixed(decimals) => format!("{:.*}", decimals, value),
+            FloatFormat::Scientific => format!("{:e}", value),
+            FloatFormat::Shortest => format!("{}", value),
+        }
+    }
+}
+
+/// Options shared by the aggregators. `Default` matches the plain `aggregate_*` functions.
+#[derive(Debug, Clone)]
+pub struct AggregateOptions {
+    /// Classify each bucket with `pattern_from_ohlc_cfg`; when false `pattern` is left empty.
+    pub compute_patterns: bool,
+    pub candle_config: CandleConfig,
+    pub session_config: SessionConfig,
+    /// Session open/close become the median of the first/last `boundary_smoothing`
+    /// bars' opens/closes. `1` (and `0`) use the single boundary bar.
+    pub boundary_smoothing: usize,
+    /// Where one trading day ends and the next begins, for both sessions and days.
+    pub day_boundary: DayBoundary,
+    pub sort_order: SortOrder,
+}
+
+impl Default for AggregateOptions {
+    fn default() -> Self {
+        AggregateOptions {
+            compute_patterns: true,
+            candle_config: CandleConfig::default(),
+            session_config: SessionConfig::default(),
+            boundary_smoothing: 1,
+            day_boundary: DayBoundary::default(),
+            sort_order: SortOrder::default(),
+        }
+    }
+}
+
+/// Output order of aggregated rows. Ascending is chronological.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+    /// Leave rows in whatever order the aggregator built them (not stable across runs).
+    Unsorted,
+}
+
+impl SortOrder {
+    /// Sort `rows` by `cmp` (the ascending comparison) in this order.
+    pub fn apply<T>(&self, rows: &mut [T], mut cmp: impl FnMut(&T, &T) -> std::cmp::Ordering) {
+        match self {
+            SortOrder::Ascending => rows.sort_by(cmp),
+            SortOrder::Descending => rows.sort_by(|a, b| cmp(b, a)),
+            SortOrder::Unsorted => {}
+        }
+    }
+}
+
+/// Trading-day cutoff. Bars stamped at or after `cutoff_hour` belong to the next
+/// day, so an overnight session that wraps midnight keeps one date. `0` (the
+/// default) is the calendar date.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DayBoundary {
+    pub cutoff_hour: u32,
+}
+
+impl DayBoundary {
+    /// Trading date of `ts` in the timestamp's own date format, so `24:00` and the
+    /// next day's `00:00` get the same key.
+    pub fn date_key(&self, ts: &str) -> String {
+        date_key_like(ts, trading_date(ts, self))
+    }
+
+    /// `date_key` for session grouping. A midnight-hour bar that `cfg` puts in the
+    /// session running at 23:00 stays on that session's trading day.
+    pub fn session_date_key(&self, ts: &str, cfg: &SessionConfig) -> String {
+        match parse_ts_to_naive(ts) {
+            Some(dt) if dt.hour() == 0 && midnight_continues_evening(cfg) => {
+                date_key_like(ts, Some(self.trading_day(dt - Duration::hours(1))))
+            }
+            _ => self.date_key(ts),
+        }
+    }
+
+    fn trading_day(&self, dt: NaiveDateTime) -> NaiveDate {
+        if self.cutoff_hour > 0 && dt.hour() >= self.cutoff_hour {
+            dt.date() + Duration::days(1)
+        } else {
+            dt.date()
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+fn midnight_continues_evening(cfg: &SessionConfig) -> bool {
+    cfg.midnight_session != Session::Unknown
+        && Session::from_hour_with_config(23, cfg) == cfg.midnight_session
+}
+
+// `date` in the date layout of `ts`; the raw date part when it didn't parse.
+fn date_key_like(ts: &str, date: Option<NaiveDate>) -> String {
+    let raw = ts.split(['T', ' ']).next().unwrap_or("").trim();
+    let fmt = if raw.contains('.') { "%Y.%m.%d" } else { "%Y-%m-%d" };
+    match date {
+        Some(date) => date.format(fmt).to_string(),
+        None => raw.to_string(),
+    }
+}
+
+/// Trading day a bar belongs to under `boundary`.
+pub fn trading_date(ts: &str, boundary: &DayBoundary) -> Option<NaiveDate> {
+    parse_ts_to_naive(ts).map(|dt| boundary.trading_day(dt))
+}
+
+/// Consecutive runs of bars sharing a trading day, as `(date, bars)`. The input must
+/// be in time order; an out-of-order bar starts a new chunk for its day. Use
+/// `TradingDayChunks::sorted` to sort first.
+pub struct TradingDayChunks<'a> {
+    data: &'a [MarketData],
+    boundary: DayBoundary,
+}
+
+impl<'a> TradingDayChunks<'a> {
+    pub fn new(data: &'a [MarketData], boundary: DayBoundary) -> Self {
+        TradingDayChunks { data, boundary }
+    }
+
+    /// Sort `data` by parsed timestamp in place, then chunk it.
+    pub fn sorted(data: &'a mut [MarketData], boundary: DayBoundary) -> Self {
+        data.sort_by_key(|r| parse_ts_to_naive(&r.timestamp));
+        TradingDayChunks::new(data, boundary)
+    }
+}
+
+impl<'a> Iterator for TradingDayChunks<'a> {
+    type Item = (String, &'a [MarketData]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.data.first()?;
+        let date = self.boundary.date_key(&first.timestamp);
+        let len = self.data.iter()
+            .position(|r| self.boundary.date_key(&r.timestamp) != date)
+            .unwrap_or(self.data.len());
+        let (chunk, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some((date, chunk))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct MarketData {
     pub timestamp: String,
     pub open: f64,
@@ -18,6 +206,10 @@ pub struct MarketData {
     pub low: f64,
     pub close: f64,
     pub volume: f64,
+    /// File the bar was read from, when the engine tags sources. Not part of
+    /// the default CSV output.
+    #[serde(skip)]
+    pub source: Option<String>,
 }
 
 impl CsvRecord for MarketData {
@@ -26,99 +218,589 @@ impl CsvRecord for MarketData {
     }
 
     fn record(&self) -> Vec<String> {
+        self.record_fmt(FloatFormat::default())
+    }
+
+    fn record_fmt(&self, fmt: FloatFormat) -> Vec<String> {
         vec![
             self.timestamp.clone(),
-            format!("{:.6}", self.open),
-            format!("{:.6}", self.high),
-            format!("{:.6}", self.low),
-            format!("{:.6}", self.close),
-            format!("{:.6}", self.volume),
+            fmt.format(self.open),
+            fmt.format(self.high),
+            fmt.format(self.low),
+            fmt.format(self.close),
+            fmt.format(self.volume),
         ]
     }
 }
 
-// Implement Serialize for MarketData to satisfy the CsvRecord trait bound
-impl Serialize for MarketData {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        use serde::ser::SerializeStruct;
-        let mut state = serializer.serialize_struct("MarketData", 6)?;
-        state.serialize_field("timestamp", &self.timestamp)?;
-        state.serialize_field("open", &self.open)?;
-        state.serialize_field("high", &self.high)?;
-        state.serialize_field("low", &self.low)?;
-        state.serialize_field("close", &self.close)?;
-        state.serialize_field("volume", &self.volume)?;
-        state.end()
+/// Which part comes first in a column holding both date and time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateTimeOrder {
+    #[default]
+    DateFirst,
+    TimeFirst,
+}
+
+/// Where `fetch_from_csv` finds each field. `Default` is the MetaTrader export layout:
+/// date, time, open, high, low, close, tick volume. Pass a custom one to
+/// `DataEngine::with_column_map` or `fetch_from_csv_with_map`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnMap {
+    pub date_index: usize,
+    /// `None` when the date column holds the time as well, e.g. `2023.03.27 11:00:00`.
+    pub time_index: Option<usize>,
+    pub open_index: usize,
+    pub high_index: usize,
+    pub low_index: usize,
+    pub close_index: usize,
+    /// `None` for exports without a volume column; bars then carry a volume of 0.
+    pub volume_index: Option<usize>,
+    /// Order of the two parts of a combined date/time column.
+    pub order: DateTimeOrder,
+    /// Separator between the parts of a combined date/time column.
+    pub separator: char,
+}
+
+impl Default for ColumnMap {
+    fn default() -> Self {
+        ColumnMap {
+            date_index: 0,
+            time_index: Some(1),
+            open_index: 2,
+            high_index: 3,
+            low_index: 4,
+            close_index: 5,
+            volume_index: Some(6),
+            order: DateTimeOrder::DateFirst,
+            separator: ' ',
+        }
+    }
+}
+
+impl ColumnMap {
+    /// `time, date, open, high, low, close, volume`.
+    pub fn time_first() -> Self {
+        ColumnMap { date_index: 1, time_index: Some(0), ..ColumnMap::default() }
+    }
+
+    /// One combined date/time column followed by open, high, low, close, volume.
+    pub fn combined(order: DateTimeOrder, separator: char) -> Self {
+        ColumnMap {
+            date_index: 0,
+            time_index: None,
+            open_index: 1,
+            high_index: 2,
+            low_index: 3,
+            close_index: 4,
+            volume_index: Some(5),
+            order,
+            separator,
+        }
+    }
+
+    /// Column map from header names, matched case-insensitively and with MetaTrader's
+    /// `<...>` brackets ignored. `None` when no date and full set of prices is found;
+    /// a volume column is optional. A date column without a time column is read as a
+    /// full timestamp.
+    pub fn from_headers(headers: &StringRecord) -> Result<Option<Self>, Box<dyn Error>> {
+        let positions: HashMap<String, usize> = header_positions(headers)?
+            .into_iter()
+            .map(|(name, i)| (name.trim_matches(|c| c == '<' || c == '>').to_string(), i))
+            .collect();
+        let find = |aliases: &[&str]| aliases.iter().find_map(|alias| positions.get(*alias).copied());
+
+        let time_index = find(&["time"]);
+        let date_index = match time_index {
+            Some(_) => find(&["date", "day"]),
+            None => find(&["datetime", "timestamp", "date"]),
+        };
+        let (Some(date_index), Some(open_index), Some(high_index), Some(low_index), Some(close_index)) = (
+            date_index,
+            find(&["open", "o"]),
+            find(&["high", "h"]),
+            find(&["low", "l"]),
+            find(&["close", "c"]),
+        ) else {
+            return Ok(None);
+        };
+
+        Ok(Some(ColumnMap {
+            date_index,
+            time_index,
+            open_index,
+            high_index,
+            low_index,
+            close_index,
+            // Tick volume first: MetaTrader's <VOL> is real volume, usually all zeros
+            volume_index: find(&["tickvol", "tick_volume", "volume", "vol"]),
+            ..ColumnMap::default()
+        }))
+    }
+
+    /// `<date>T<time>` assembled from `record`.
+    fn timestamp(&self, record: &StringRecord) -> Result<String, Box<dyn Error>> {
+        let (date, time) = match self.time_index {
+            Some(time_index) => (field(record, self.date_index)?, field(record, time_index)?),
+            None => {
+                let combined = field(record, self.date_index)?;
+                // Already a full timestamp, e.g. `2023-03-27T11:00:00`
+                let Some((first, second)) = combined.split_once(self.separator) else {
+                    return Ok(combined.trim().to_string());
+                };
+                match self.order {
+                    DateTimeOrder::DateFirst => (first, second),
+                    DateTimeOrder::TimeFirst => (second, first),
+                }
+            }
+        };
+        Ok(format!("{}T{}", date.trim(), time.trim()))
+    }
+}
+
+fn field(record: &StringRecord, index: usize) -> Result<&str, Box<dyn Error>> {
+    record.get(index).ok_or_else(|| format!("missing column {}", index).into())
+}
+
+// With `decimal_comma`, `1.234,56` reads as 1234.56.
+fn parse_number(record: &StringRecord, index: usize, decimal_comma: bool) -> Result<f64, Box<dyn Error>> {
+    let raw = field(record, index)?;
+    let value: f64 = if decimal_comma { raw.replace('.', "").replace(',', ".").parse() } else { raw.parse() }.map_err(|e| format!("column {}: '{}': {}", index, raw, e))?;
+    if !value.is_finite() {
+        return Err(format!("column {}: '{}' is not a finite number", index, raw).into());
+    }
+    Ok(value)
+}
+
+/// A bar whose prices or volume can't be real. Each variant carries the bar's timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OhlcError {
+    HighBelowLow(String),
+    OpenOutOfRange(String),
+    CloseOutOfRange(String),
+    NegativeVolume(String),
+}
+
+impl fmt::Display for OhlcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OhlcError::HighBelowLow(ts) => write!(f, "{}: high below low", ts),
+            OhlcError::OpenOutOfRange(ts) => write!(f, "{}: open outside [low, high]", ts),
+            OhlcError::CloseOutOfRange(ts) => write!(f, "{}: close outside [low, high]", ts),
+            OhlcError::NegativeVolume(ts) => write!(f, "{}: negative volume", ts),
+        }
+    }
+}
+
+impl Error for OhlcError {}
+
+pub fn validate_ohlc(bar: &MarketData) -> Result<(), OhlcError> {
+    let ts = || bar.timestamp.clone();
+    if bar.high < bar.low {
+        return Err(OhlcError::HighBelowLow(ts()));
+    }
+    if bar.open < bar.low || bar.open > bar.high {
+        return Err(OhlcError::OpenOutOfRange(ts()));
+    }
+    if bar.close < bar.low || bar.close > bar.high {
+        return Err(OhlcError::CloseOutOfRange(ts()));
+    }
+    if bar.volume < 0.0 {
+        return Err(OhlcError::NegativeVolume(ts()));
+    }
+    Ok(())
+}
+
+/// Sort `data` by timestamp and drop rows whose timestamp repeats, keeping the last
+/// occurrence of each (the most recent correction). Rows with unparseable timestamps
+/// are kept, first. Returns how many rows were dropped.
+pub fn dedup_by_timestamp(data: &mut Vec<MarketData>) -> usize {
+    let before = data.len();
+    let mut keyed: Vec<(Option<NaiveDateTime>, MarketData)> = data.drain(..)
+        .map(|r| (parse_ts_to_naive(&r.timestamp), r))
+        .collect();
+
+    // Stable sort, then dedup from the back so the last of each run survives.
+    keyed.sort_by_key(|(ts, _)| *ts);
+    keyed.reverse();
+    keyed.dedup_by(|a, b| a.0.is_some() && a.0 == b.0);
+    keyed.reverse();
+
+    data.extend(keyed.into_iter().map(|(_, r)| r));
+    before - data.len()
+}
+
+/// Quick health check of a loaded series; see `summarize`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub rows: usize,
+    // Earliest and latest parseable timestamps, as written in the data
+    pub first_timestamp: Option<String>,
+    pub last_timestamp: Option<String>,
+    // 0.0 for an empty series
+    pub mean_close: f64,
+    pub min_close: f64,
+    pub max_close: f64,
+    pub total_volume: f64,
+    pub unparseable_timestamps: usize,
+    pub duplicate_timestamps: usize, // Rows repeating an earlier row's timestamp
+}
+
+/// Row count, time span, close statistics and timestamp problems of `data`.
+pub fn summarize(data: &[MarketData]) -> Summary {
+    let mut summary = Summary { rows: data.len(), ..Summary::default() };
+    if data.is_empty() {
+        return summary;
+    }
+
+    let mut seen = HashSet::new();
+    let mut first: Option<(NaiveDateTime, &str)> = None;
+    let mut last: Option<(NaiveDateTime, &str)> = None;
+    for r in data {
+        let Some(dt) = parse_ts_to_naive(&r.timestamp) else {
+            summary.unparseable_timestamps += 1;
+            continue;
+        };
+        if !seen.insert(dt) {
+            summary.duplicate_timestamps += 1;
+        }
+        if first.is_none_or(|(f, _)| dt < f) {
+            first = Some((dt, &r.timestamp));
+        }
+        if last.is_none_or(|(l, _)| dt > l) {
+            last = Some((dt, &r.timestamp));
+        }
+    }
+    summary.first_timestamp = first.map(|(_, ts)| ts.to_string());
+    summary.last_timestamp = last.map(|(_, ts)| ts.to_string());
+
+    summary.mean_close = data.iter().map(|r| r.close).sum::<f64>() / data.len() as f64;
+    summary.min_close = data.iter().map(|r| r.close).fold(f64::INFINITY, f64::min);
+    summary.max_close = data.iter().map(|r| r.close).fold(f64::NEG_INFINITY, f64::max);
+    summary.total_volume = data.iter().map(|r| r.volume).sum();
+    summary
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |ts: &Option<String>| ts.clone().unwrap_or_else(|| "-".to_string());
+        writeln!(f, "rows:                   {}", self.rows)?;
+        writeln!(f, "first timestamp:        {}", or_dash(&self.first_timestamp))?;
+        writeln!(f, "last timestamp:         {}", or_dash(&self.last_timestamp))?;
+        writeln!(f, "close mean/min/max:     {:.6} / {:.6} / {:.6}", self.mean_close, self.min_close, self.max_close)?;
+        writeln!(f, "total volume:           {:.6}", self.total_volume)?;
+        writeln!(f, "unparseable timestamps: {}", self.unparseable_timestamps)?;
+        write!(f, "duplicate timestamps:   {}", self.duplicate_timestamps)
     }
 }
 
-pub struct DataEngine;
+#[derive(Debug, Clone, Default)]
+pub struct DataEngine {
+    tag_source: bool,
+    columns: ColumnMap,
+    validate: bool,
+    decimal_comma: bool,
+}
 
 impl DataEngine {
     pub fn new() -> Self {
-        DataEngine
+        DataEngine::default()
+    }
+
+    /// Record the source file name on every loaded bar (`MarketData::source`).
+    pub fn with_source_tagging(mut self, enabled: bool) -> Self {
+        self.tag_source = enabled;
+        self
+    }
+
+    pub fn with_column_map(mut self, columns: ColumnMap) -> Self {
+        self.columns = columns;
+        self
     }
-    
+
+    /// Read prices written with a decimal comma and `.` thousands separators, e.g.
+    /// `1.234,56`. Only applies to files not delimited by commas.
+    pub fn with_decimal_comma(mut self, enabled: bool) -> Self {
+        self.decimal_comma = enabled;
+        self
+    }
+
+    /// Reject rows that fail `validate_ohlc` like any other malformed row.
+    pub fn with_validation(mut self, enabled: bool) -> Self {
+        self.validate = enabled;
+        self
+    }
+
     pub fn fetch_from_csv(&self, path: &Path) -> Result<Vec<MarketData>, Box<dyn Error>> {
-        let mut delimiter = b',';
-        let mut rdr = ReaderBuilder::new()
-            .has_headers(false)
-            .from_reader(File::open(path)?);
-
-        // Peek at the first record to determine the delimiter.
-        if let Some(Ok(record)) = rdr.records().next() {
-            // A common heuristic is to check the number of fields.
-            // If it's not a common number like 8 or 9, it may be delimited by tabs.
-            if record.len() < 8 {
-                delimiter = b'\t';
+        let (records, _) = self.fetch_from_csv_lenient(path, true)?;
+        Ok(records)
+    }
+
+    /// `fetch_from_csv` with `columns` in place of this engine's column map.
+    pub fn fetch_from_csv_with_map(&self, path: &Path, columns: &ColumnMap) -> Result<Vec<MarketData>, Box<dyn Error>> {
+        self.clone().with_column_map(*columns).fetch_from_csv(path)
+    }
+
+    /// `fetch_from_csv` with columns located by header name (see `ColumnMap::from_headers`),
+    /// so reordered exports load correctly. Falls back to this engine's positional map
+    /// when the header isn't recognized.
+    pub fn fetch_from_csv_by_header(&self, path: &Path) -> Result<Vec<MarketData>, Box<dyn Error>> {
+        let headers = self.data_reader(path)?.0.headers()?.clone();
+        let columns = ColumnMap::from_headers(&headers)?.unwrap_or(self.columns);
+        self.fetch_from_csv_with_map(path, &columns)
+    }
+
+    /// Load `path`, collecting per-row problems (unparseable or non-finite numbers,
+    /// missing columns) as `(line, reason)`. When `strict` the first problem is
+    /// returned as an error instead; otherwise bad rows are skipped and reported.
+    #[allow(clippy::type_complexity)]
+    pub fn fetch_from_csv_lenient(
+        &self,
+        path: &Path,
+        strict: bool,
+    ) -> Result<(Vec<MarketData>, Vec<(usize, String)>), Box<dyn Error>> {
+        let mut records = Vec::new();
+        let mut skipped = Vec::new();
+        for (line, parsed) in self.rows(path)? {
+            match parsed {
+                Ok(row) => records.push(row),
+                Err(e) if strict => return Err(format!("line {}: {}", line, e).into()),
+                Err(e) => skipped.push((line, e.to_string())),
             }
         }
-        
+
+        Ok((records, skipped))
+    }
+
+    /// Load a file previously written with `write_csv` (a comma-separated
+    /// `timestamp,open,high,low,close,volume` header), matching columns by name.
+    pub fn fetch_serialized(&self, path: &Path) -> Result<Vec<MarketData>, Box<dyn Error>> {
+        let mut rdr = ReaderBuilder::new().trim(Trim::All).from_reader(open_without_bom(path)?);
+        let source = self.source_name(path);
+        let mut records = Vec::new();
+        for row in rdr.deserialize() {
+            let mut bar: MarketData = row?;
+            bar.source = source.clone();
+            records.push(bar);
+        }
+        Ok(records)
+    }
+
+    /// Parse `path` lazily, one bar per item, without holding the whole file in memory.
+    /// Delimiter detection, column mapping and validation match `fetch_from_csv`; a bad
+    /// row yields an `Err` (prefixed with its line) and iteration can carry on past it.
+    pub fn stream_from_csv(
+        &self,
+        path: &Path,
+    ) -> Result<impl Iterator<Item = Result<MarketData, Box<dyn Error>>>, Box<dyn Error>> {
+        Ok(self
+            .rows(path)?
+            .map(|(line, parsed)| parsed.map_err(|e| format!("line {}: {}", line, e).into())))
+    }
+
+    // Parsed data rows of `path` paired with their line numbers; empty rows are skipped.
+    #[allow(clippy::type_complexity)]
+    fn rows(
+        &self,
+        path: &Path,
+    ) -> Result<impl Iterator<Item = (usize, Result<MarketData, Box<dyn Error>>)>, Box<dyn Error>> {
+        let (rdr, delimiter) = self.data_reader(path)?;
+        let decimal_comma = self.decimal_comma && delimiter != b',';
+        let source = self.source_name(path);
+        let columns = self.columns;
+        let validate = self.validate;
+
+        // The reader has already taken the header row
+        Ok(rdr.into_records().filter_map(move |result| {
+            let (line, parsed) = match result {
+                Ok(record) => {
+                    if is_empty_record(&record) {
+                        return None;
+                    }
+                    let record = without_trailing_empty(&record);
+                    let parsed = parse_row(&columns, &record, &source, decimal_comma).and_then(|row| {
+                        if validate {
+                            validate_ohlc(&row)?;
+                        }
+                        Ok(row)
+                    });
+                    (record.position().map(|p| p.line()), parsed)
+                }
+                Err(e) => (e.position().map(|p| p.line()), Err(e.into())),
+            };
+            Some((line.unwrap_or_default() as usize, parsed))
+        }))
+    }
+
+    // Reader over the data rows of `path`, with the delimiter sniffed from its first
+    // few non-empty lines. Returns the delimiter alongside.
+    fn data_reader(&self, path: &Path) -> Result<(csv::Reader<impl Read>, u8), Box<dyn Error>> {
+        let delimiter = sniff_delimiter(path)?;
+
         // Now, create the final reader with the determined delimiter and headers.
-        let mut rdr = ReaderBuilder::new()
+        // Flexible so a trailing delimiter on some lines but not others isn't an error;
+        // short rows still fail in `parse_row` on the missing column.
+        let rdr = ReaderBuilder::new()
             .delimiter(delimiter)
             .trim(Trim::All)
-            .from_reader(File::open(path)?);
+            .comment(Some(b'#'))
+            .flexible(true)
+            .from_reader(open_without_bom(path)?);
+        Ok((rdr, delimiter))
+    }
 
-        let mut records = Vec::new();
-        let mut raw_records = rdr.records();
-
-        // Skip the header row
-        if raw_records.next().is_some() {
-            // Process remaining records
-            for result in raw_records {
-                let record = result?;
-                
-                // Manually map columns by index based on your provided format
-                let date = &record[0];
-                let time = &record[1];
-                let open: f64 = record[2].parse()?;
-                let high: f64 = record[3].parse()?;
-                let low: f64 = record[4].parse()?;
-                let close: f64 = record[5].parse()?;
-                let volume: f64 = record[6].parse()?; // Correctly read TICKVOL as volume
-
-                let timestamp = format!("{}T{}", date, time);
-
-
-                records.push(MarketData {
-                    timestamp,
-                    open,
-                    high,
-                    low,
-                    close,
-                    volume,
-                });
-            }
+    fn source_name(&self, path: &Path) -> Option<String> {
+        self.tag_source.then(|| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string())
+        })
+    }
+
+    /// Load several files with `fetch_from_csv` and merge them into one series sorted
+    /// by timestamp. A timestamp present in more than one file keeps the bar from the
+    /// earliest file in `paths`.
+    pub fn fetch_from_csvs(&self, paths: &[&Path]) -> Result<Vec<MarketData>, Box<dyn Error>> {
+        let mut keyed: Vec<(Option<NaiveDateTime>, MarketData)> = Vec::new();
+        for path in paths {
+            keyed.extend(self.fetch_from_csv(path)?.into_iter().map(|r| (parse_ts_to_naive(&r.timestamp), r)));
+        }
+
+        // Stable, so equal timestamps stay in file order and dedup keeps the first.
+        keyed.sort_by_key(|(ts, _)| *ts);
+        keyed.dedup_by(|later, earlier| later.0.is_some() && later.0 == earlier.0);
+        Ok(keyed.into_iter().map(|(_, r)| r).collect())
+    }
+
+    /// `fetch_from_csv` with every timestamp moved by `offset_hours`, e.g. from UTC
+    /// to exchange time so sessions bucket correctly. Timestamps keep their original
+    /// layout and the date rolls over when the shift crosses midnight.
+    pub fn fetch_from_csv_with_tz(&self, path: &Path, offset_hours: i32) -> Result<Vec<MarketData>, Box<dyn Error>> {
+        let mut records = self.fetch_from_csv(path)?;
+        for record in &mut records {
+            record.timestamp = shift_timestamp(&record.timestamp, offset_hours)
+                .ok_or_else(|| format!("Unparseable timestamp: {}", record.timestamp))?;
         }
-        
         Ok(records)
     }
 }
 
+/// `ts` moved by `hours`, written back in the same date/time layout it came in.
+pub fn shift_timestamp(ts: &str, hours: i32) -> Option<String> {
+    let shifted = parse_ts_to_naive(ts)? + Duration::hours(hours as i64);
+    Some(format_like(ts, shifted))
+}
+
+/// `dt` written in the date/time layout of `ts`.
+pub fn format_like(ts: &str, dt: NaiveDateTime) -> String {
+    let s = ts.trim();
+    let (date, rest) = match s.find(['T', ' ']) {
+        Some(idx) => (&s[..idx], Some((&s[idx..idx + 1], &s[idx + 1..]))),
+        None => (s, None),
+    };
+    let date_fmt = if date.contains('.') { "%Y.%m.%d" } else { "%Y-%m-%d" };
+    let fmt = match rest {
+        // A bare date only stays bare at midnight.
+        None if dt.time() == chrono::NaiveTime::MIN => date_fmt.to_string(),
+        None => format!("{}T%H:%M:%S", date_fmt),
+        Some((sep, time)) => {
+            let time_fmt = match (time.matches(':').count(), time.contains('.')) {
+                (1, _) => "%H:%M",
+                (_, true) => "%H:%M:%S%.f",
+                _ => "%H:%M:%S",
+            };
+            format!("{}{}{}", date_fmt, sep, time_fmt)
+        }
+    };
+    dt.format(&fmt).to_string()
+}
+
+fn parse_row(
+    cols: &ColumnMap,
+    record: &StringRecord,
+    source: &Option<String>,
+    decimal_comma: bool,
+) -> Result<MarketData, Box<dyn Error>> {
+    let number = |index| parse_number(record, index, decimal_comma);
+    Ok(MarketData {
+        timestamp: cols.timestamp(record)?,
+        open: number(cols.open_index)?,
+        high: number(cols.high_index)?,
+        low: number(cols.low_index)?,
+        close: number(cols.close_index)?,
+        volume: match cols.volume_index {
+            Some(index) => number(index)?, // TICKVOL in the default layout
+            None => 0.0,
+        },
+        source: source.clone(),
+    })
+}
+
+// Of `DELIMITERS`, the one splitting the first few non-empty lines into the same
+// number of fields most often; more fields, then list order, break ties. Comma when
+// no candidate splits anything.
+fn sniff_delimiter(path: &Path) -> Result<u8, Box<dyn Error>> {
+    let mut best = (b',', 0, 0);
+    for &delimiter in &DELIMITERS {
+        let mut rdr = ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .comment(Some(b'#'))
+            .flexible(true)
+            .from_reader(open_without_bom(path)?);
+        let widths: Vec<usize> = rdr.records()
+            .filter_map(Result::ok)
+            .filter(|r| !is_empty_record(r))
+            .take(SNIFF_LINES)
+            .map(|r| without_trailing_empty(&r).len())
+            .collect();
+
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        for &w in &widths {
+            *counts.entry(w).or_insert(0) += 1;
+        }
+        let Some((&width, &lines)) = counts.iter().max_by_key(|(w, n)| (**n, **w)) else { continue };
+        if width > 1 && (lines, width) > (best.1, best.2) {
+            best = (delimiter, lines, width);
+        }
+    }
+    Ok(best.0)
+}
+
+fn is_empty_record(record: &StringRecord) -> bool {
+    record.iter().all(|field| field.trim().is_empty())
+}
+
+// Drops the empty field a trailing delimiter leaves at the end of a line.
+fn without_trailing_empty(record: &StringRecord) -> StringRecord {
+    let mut record = record.clone();
+    if record.len() > 1 && record.get(record.len() - 1).is_some_and(|f| f.trim().is_empty()) {
+        record.truncate(record.len() - 1);
+    }
+    record
+}
+
+/// Lower-cased header name to column index. A trailing empty header is ignored;
+/// a name that appears twice is an error, since either column could be meant.
+pub fn header_positions(headers: &StringRecord) -> Result<HashMap<String, usize>, Box<dyn Error>> {
+    let mut positions = HashMap::new();
+    for (i, name) in without_trailing_empty(headers).iter().enumerate() {
+        let key = name.trim().to_lowercase();
+        if let Some(first) = positions.insert(key.clone(), i) {
+            return Err(format!("Duplicate header '{}' in columns {} and {}", key, first, i).into());
+        }
+    }
+    Ok(positions)
+}
+
+// Opens a file for reading, skipping a leading UTF-8 BOM if present.
+fn open_without_bom(path: &Path) -> Result<impl Read, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(UTF8_BOM) {
+        reader.consume(UTF8_BOM.len());
+    }
+    Ok(reader)
+}
+
 pub fn write_csv<T: CsvRecord + serde::Serialize + std::fmt::Debug>(
     records: &[T],
     file_path: &str,
@@ -129,15 +811,201 @@ pub fn write_csv<T: CsvRecord + serde::Serialize + std::fmt::Debug>(
 
     writer.write_record(T::headers())?;
 
+    // Rows come from `record()` so they always line up with `headers()`.
     for record in records.iter() {
-        if let Err(e) = writer.serialize(record) {
-            eprintln!("Error serializing record: {:?} -> {}", record, e);
+        if let Err(e) = writer.write_record(record.record()) {
+            eprintln!("Error writing record: {:?} -> {}", record, e);
         }
     }
     writer.flush()?;
     Ok(())
 }
 
+/// `write_csv` with floats written to `decimals` places instead of 6, e.g. 2 for
+/// index points or 8 for crypto.
+pub fn write_csv_with_precision<T: CsvRecord>(
+    records: &[T],
+    file_path: &str,
+    decimals: usize,
+) -> Result<(), Box<dyn Error>> {
+    write_csv_fmt(records, file_path, FloatFormat::Fixed(decimals))
+}
+
+/// `write_csv` with floats written in `fmt`; `FloatFormat::Shortest` or `Scientific`
+/// keep very small prices exact.
+pub fn write_csv_fmt<T: CsvRecord>(records: &[T], file_path: &str, fmt: FloatFormat) -> Result<(), Box<dyn Error>> {
+    let mut writer = WriterBuilder::new().from_path(file_path)?;
+    writer.write_record(T::headers())?;
+    for record in records {
+        writer.write_record(record.record_fmt(fmt))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// `serialize_with` helper for optional numbers: `None`, NaN and infinities are
+/// written as a missing value (`null` in JSON) instead of an invalid number.
+pub fn serialize_finite<S: serde::Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(v) if v.is_finite() => serializer.serialize_some(v),
+        _ => serializer.serialize_none(),
+    }
+}
+
+/// Write `records` as a pretty-printed JSON array. Missing and non-finite numbers
+/// come out as `null`.
+pub fn write_json<T: Serialize>(records: &[T], file_path: &str) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(file_path)?);
+    serde_json::to_writer_pretty(&mut writer, records)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write `records` as a Parquet file, one column per serde field in declaration order
+/// (the table types declare their CSV columns first, in `headers()` order). Column types
+/// follow how each field serializes: `f64` is Float64, integers Int64, `bool` Boolean and
+/// strings and unit enums Utf8. A column is nullable when any row serializes it as
+/// `None`; a column that is `None` in every row is a nullable Float64, since the only
+/// optional fields are numbers. `records` must be non-empty structs.
+#[cfg(feature = "parquet")]
+pub fn write_parquet<T: Serialize>(records: &[T], file_path: &str) -> Result<(), Box<dyn Error>> {
+    use std::sync::Arc;
+    use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use serde_json::{Map, Value};
+
+    let rows = records.iter()
+        .map(|r| match serde_json::to_value(r)? {
+            Value::Object(map) => Ok(map),
+            other => Err(format!("write_parquet needs struct records, got {}", other).into()),
+        })
+        .collect::<Result<Vec<Map<String, Value>>, Box<dyn Error>>>()?;
+    let names: Vec<String> = rows.first()
+        .ok_or("write_parquet needs at least one record to derive a schema")?
+        .keys().cloned().collect();
+
+    let mut fields = Vec::with_capacity(names.len());
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(names.len());
+    for name in &names {
+        let values: Vec<&Value> = rows.iter().map(|r| r.get(name).unwrap_or(&Value::Null)).collect();
+        let nullable = values.iter().any(|v| v.is_null());
+        let data_type = match values.iter().find(|v| !v.is_null()) {
+            None => DataType::Float64,
+            Some(Value::Bool(_)) => DataType::Boolean,
+            // serde_json keeps integers and floats apart, so this is the field's type, not its value.
+            Some(Value::Number(n)) if n.is_f64() => DataType::Float64,
+            Some(Value::Number(_)) => DataType::Int64,
+            Some(Value::String(_)) => DataType::Utf8,
+            Some(other) => return Err(format!("{}: unsupported value {}", name, other).into()),
+        };
+        let mismatch = || format!("{}: rows disagree on the column type", name);
+
+        let column: ArrayRef = match data_type {
+            DataType::Boolean => Arc::new(values.iter()
+                .map(|v| if v.is_null() { Ok(None) } else { v.as_bool().map(Some).ok_or_else(mismatch) })
+                .collect::<Result<BooleanArray, _>>()?),
+            DataType::Int64 => Arc::new(values.iter()
+                .map(|v| if v.is_null() { Ok(None) } else { v.as_i64().map(Some).ok_or_else(mismatch) })
+                .collect::<Result<Int64Array, _>>()?),
+            DataType::Float64 => Arc::new(values.iter()
+                .map(|v| if v.is_null() { Ok(None) } else { v.as_f64().map(Some).ok_or_else(mismatch) })
+                .collect::<Result<Float64Array, _>>()?),
+            _ => Arc::new(values.iter()
+                .map(|v| if v.is_null() { Ok(None) } else { v.as_str().map(Some).ok_or_else(mismatch) })
+                .collect::<Result<StringArray, _>>()?),
+        };
+        fields.push(Field::new(name, data_type, nullable));
+        columns.push(column);
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut writer = ArrowWriter::try_new(File::create(file_path)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// `write_csv` for bars with an extra trailing `source` column.
+pub fn write_csv_with_source(records: &[MarketData], file_path: &str) -> Result<(), Box<dyn Error>> {
+    let mut writer = WriterBuilder::new().from_path(file_path)?;
+
+    let mut headers = MarketData::headers().to_vec();
+    headers.push("source");
+    writer.write_record(&headers)?;
+
+    for record in records.iter() {
+        let mut values = record.record();
+        values.push(record.source.clone().unwrap_or_default());
+        writer.write_record(&values)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write only the named `columns`, in the given order, using each record's
+/// `headers()`/`record()` pairs. Unknown column names are an error.
+pub fn write_csv_selected<T: CsvRecord>(
+    records: &[T],
+    file_path: &str,
+    columns: &[&str],
+) -> Result<(), Box<dyn Error>> {
+    let headers = T::headers();
+    let indices = columns.iter()
+        .map(|col| {
+            headers.iter()
+                .position(|h| h == col)
+                .ok_or_else(|| format!("Unknown column '{}', expected one of: {}", col, headers.join(", ")))
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+
+    let mut writer = WriterBuilder::new().from_path(file_path)?;
+    writer.write_record(columns)?;
+
+    for record in records.iter() {
+        let values = record.record();
+        writer.write_record(indices.iter().map(|&i| values[i].as_str()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write one `<key>.csv` per distinct `key_fn` value into `dir` (created if missing),
+/// each with the header row. Rows keep their input order within a file.
+pub fn write_csv_partitioned<T: CsvRecord>(
+    records: &[T],
+    dir: &Path,
+    key_fn: impl Fn(&T) -> String,
+) -> Result<(), Box<dyn Error>> {
+    let mut groups: BTreeMap<String, Vec<&T>> = BTreeMap::new();
+    for record in records {
+        groups.entry(key_fn(record)).or_default().push(record);
+    }
+
+    std::fs::create_dir_all(dir)?;
+    for (key, rows) in groups {
+        let mut writer = WriterBuilder::new().from_path(dir.join(format!("{}.csv", key)))?;
+        writer.write_record(T::headers())?;
+        for record in rows {
+            writer.write_record(record.record())?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Grouping key for the ISO week holding `ndt`, e.g. `"2023-W05"`. Uses the ISO
+/// year, so late-December/early-January days land in the right week.
+pub fn iso_week_key(ndt: &NaiveDateTime) -> String {
+    iso_week_key_date(ndt.date())
+}
+
+pub fn iso_week_key_date(date: NaiveDate) -> String {
+    let iso = date.iso_week();
+    format!("{}-W{:02}", iso.year(), iso.week())
+}
+
 pub fn parse_ts_to_naive(ts: &str) -> Option<NaiveDateTime> {
     let s = ts.trim();
 
@@ -159,6 +1027,315 @@ pub fn parse_ts_to_naive(ts: &str) -> Option<NaiveDateTime> {
             return Some(dt);
         }
     }
-    
+
+    // Some brokers stamp midnight as "24:00" on the day that just ended.
+    if let Some(idx) = s.find(['T', ' ']) {
+        if let Some(rest) = s[idx + 1..].strip_prefix("24") {
+            let midnight = format!("{}{}00{}", &s[..idx], &s[idx..idx + 1], rest);
+            return parse_ts_to_naive(&midnight).map(|dt| dt + Duration::days(1));
+        }
+    }
+
     None
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const MT_HEADER: &str = "<DATE>\t<TIME>\t<OPEN>\t<HIGH>\t<LOW>\t<CLOSE>\t<TICKVOL>\t<VOL>\t<SPREAD>\n";
+    const MT_ROWS: &str = "2023.03.27\t11:00:00\t1757.4\t1761.1\t1755.7\t1760.1\t799\t0\t6\n\
+                           2023.03.27\t12:00:00\t1760.2\t1766.2\t1759.8\t1764.9\t1497\t0\t6\n";
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("data_engine_{}_{}", std::process::id(), name))
+    }
+
+    // Write `contents` to a temp file, hand its path to `read`, and clean up.
+    fn with_file<T>(name: &str, contents: &[u8], read: impl FnOnce(&Path) -> T) -> T {
+        let path = temp_path(name);
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        let out = read(&path);
+        std::fs::remove_file(&path).ok();
+        out
+    }
+
+    // What `write` puts in a temp file.
+    fn written(name: &str, write: impl FnOnce(&str) -> Result<(), Box<dyn Error>>) -> String {
+        let path = temp_path(name);
+        write(path.to_str().unwrap()).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        contents
+    }
+
+    fn bar(ts: &str, close: f64) -> MarketData {
+        MarketData { timestamp: ts.to_string(), open: 100.0, high: 105.0, low: 99.5, close, volume: 1500.0, source: None }
+    }
+
+    #[test]
+    fn fetch_from_csv_strips_utf8_bom() {
+        let contents = [UTF8_BOM, MT_HEADER.as_bytes(), MT_ROWS.as_bytes()].concat();
+        let (header, data) = with_file("bom.csv", &contents, |path| {
+            let mut header = String::new();
+            BufReader::new(open_without_bom(path).unwrap()).read_line(&mut header).unwrap();
+            (header, DataEngine::new().fetch_from_csv(path).unwrap())
+        });
+        assert!(header.starts_with("<DATE>"));
+
+        let last = data.last().unwrap();
+        assert_eq!(last.timestamp, "2023.03.27T12:00:00");
+        assert!(parse_ts_to_naive(&last.timestamp).is_some());
+        assert_eq!(last.open, 1760.2);
+    }
+
+    #[test]
+    fn fetch_from_csv_skips_comment_and_blank_lines() {
+        let contents = format!(
+            "# exported by broker\n# symbol: US2000\n{}{}\n2023.03.27\t13:00:00\t1764.8\t1772.3\t1764.6\t1768.2\t1797\t0\t6\n",
+            MT_HEADER, MT_ROWS,
+        );
+        let data = with_file("comments.csv", contents.as_bytes(), |path| DataEngine::new().fetch_from_csv(path).unwrap());
+
+        let last = data.last().unwrap();
+        assert_eq!(last.timestamp, "2023.03.27T13:00:00");
+        assert_eq!(last.close, 1768.2);
+        assert!(data.iter().all(|r| parse_ts_to_naive(&r.timestamp).is_some()));
+    }
+
+    #[test]
+    fn fetch_from_csv_reads_time_first_layout() {
+        let data = with_file(
+            "time_first.csv",
+            b"<TIME>\t<DATE>\t<OPEN>\t<HIGH>\t<LOW>\t<CLOSE>\t<TICKVOL>\t<VOL>\t<SPREAD>\n\
+              11:00:00\t2023.03.27\t1757.4\t1761.1\t1755.7\t1760.1\t799\t0\t6\n\
+              12:00:00\t2023.03.27\t1760.2\t1766.2\t1759.8\t1764.9\t1497\t0\t6\n",
+            |path| DataEngine::new().with_column_map(ColumnMap::time_first()).fetch_from_csv(path).unwrap(),
+        );
+
+        let last = data.last().unwrap();
+        assert_eq!(last.timestamp, "2023.03.27T12:00:00");
+        assert_eq!(last.volume, 1497.0);
+    }
+
+    #[test]
+    fn fetch_from_csv_splits_combined_time_first_column() {
+        let columns = ColumnMap::combined(DateTimeOrder::TimeFirst, ' ');
+        let data = with_file(
+            "combined_time_first.csv",
+            b"<DATETIME>\t<OPEN>\t<HIGH>\t<LOW>\t<CLOSE>\t<TICKVOL>\n\
+              11:00:00 2023.03.27\t1757.4\t1761.1\t1755.7\t1760.1\t799\n\
+              12:00:00 2023.03.27\t1760.2\t1766.2\t1759.8\t1764.9\t1497\n",
+            |path| DataEngine::new().with_column_map(columns).fetch_from_csv(path).unwrap(),
+        );
+
+        let last = data.last().unwrap();
+        assert_eq!(last.timestamp, "2023.03.27T12:00:00");
+        assert_eq!(last.open, 1760.2);
+        assert_eq!(last.volume, 1497.0);
+    }
+
+    #[test]
+    fn fetch_serialized_reads_back_write_csv() {
+        let bars = vec![bar("2023-03-27T11:00:00", 1760.1), bar("2023-03-27T12:00:00", 1764.9)];
+        let path = temp_path("roundtrip.csv");
+        write_csv(&bars, path.to_str().unwrap()).unwrap();
+
+        let read = DataEngine::new().fetch_serialized(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(read, bars);
+    }
+
+    #[test]
+    fn stream_from_csv_matches_fetch_and_reports_bad_rows() {
+        let contents = format!(
+            "{}{}2023.03.27\t13:00:00\tn/a\t1772.3\t1764.6\t1768.2\t1797\t0\t6\n\
+             2023.03.27\t14:00:00\t1768.1\t1770.0\t1765.2\t1769.4\t1203\t0\t6\n",
+            MT_HEADER, MT_ROWS,
+        );
+        let engine = DataEngine::new();
+        let (streamed, (fetched, skipped)) = with_file("stream.csv", contents.as_bytes(), |path| {
+            let streamed: Vec<_> = engine.stream_from_csv(path).unwrap().collect();
+            (streamed, engine.fetch_from_csv_lenient(path, false).unwrap())
+        });
+
+        let good: Vec<_> = streamed.iter().filter_map(|r| r.as_ref().ok()).collect();
+        assert_eq!(good.len(), fetched.len());
+        assert!(good.iter().zip(&fetched).all(|(a, b)| *a == b));
+        let bad: Vec<_> = streamed.iter().filter_map(|r| r.as_ref().err()).collect();
+        assert_eq!(bad.len(), skipped.len());
+        assert!(bad[0].to_string().starts_with("line 4:"));
+    }
+
+    #[test]
+    fn fetch_from_csv_with_map_reads_full_timestamps_without_volume() {
+        let columns = ColumnMap { volume_index: None, ..ColumnMap::combined(DateTimeOrder::DateFirst, ' ') };
+        let data = with_file(
+            "iso_no_volume.csv",
+            b"time\topen\thigh\tlow\tclose\n\
+              2023-03-27T11:00:00\t1757.4\t1761.1\t1755.7\t1760.1\n\
+              2023-03-27T12:00:00\t1760.2\t1766.2\t1759.8\t1764.9\n",
+            |path| DataEngine::new().fetch_from_csv_with_map(path, &columns).unwrap(),
+        );
+
+        let last = data.last().unwrap();
+        assert_eq!(last.timestamp, "2023-03-27T12:00:00");
+        assert!(parse_ts_to_naive(&last.timestamp).is_some());
+        assert_eq!(last.close, 1764.9);
+        assert_eq!(last.volume, 0.0);
+    }
+
+    #[test]
+    fn fetch_from_csv_by_header_follows_reordered_columns() {
+        let data = with_file(
+            "reordered.csv",
+            b"Time\tDate\tClose\tVolume\tOpen\tLow\tHigh\n\
+              11:00:00\t2023.03.27\t1760.1\t799\t1757.4\t1755.7\t1761.1\n\
+              12:00:00\t2023.03.27\t1764.9\t1497\t1760.2\t1759.8\t1766.2\n",
+            |path| DataEngine::new().fetch_from_csv_by_header(path).unwrap(),
+        );
+
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].timestamp, "2023.03.27T11:00:00");
+        assert_eq!((data[0].open, data[0].high, data[0].low, data[0].close), (1757.4, 1761.1, 1755.7, 1760.1));
+        assert_eq!(data[1].volume, 1497.0);
+    }
+
+    #[test]
+    fn column_map_from_headers_reads_metatrader_names_and_rejects_unknown() {
+        let mt = StringRecord::from(vec!["<DATE>", "<TIME>", "<OPEN>", "<HIGH>", "<LOW>", "<CLOSE>", "<TICKVOL>", "<VOL>", "<SPREAD>"]);
+        assert_eq!(ColumnMap::from_headers(&mt).unwrap(), Some(ColumnMap::default()));
+
+        let unknown = StringRecord::from(vec!["a", "b", "c", "d", "e", "f"]);
+        assert_eq!(ColumnMap::from_headers(&unknown).unwrap(), None);
+    }
+
+    #[test]
+    fn fetch_from_csv_detects_semicolons_and_reads_decimal_commas() {
+        let data = with_file(
+            "semicolon.csv",
+            b"Date;Time;Open;High;Low;Close;Volume\n\
+              2023.03.27;11:00:00;1.757,4;1.761,1;1.755,7;1.760,1;799\n\
+              2023.03.27;12:00:00;1.760,2;1.766,2;1.759,8;1.764,9;1497\n",
+            |path| DataEngine::new().with_decimal_comma(true).fetch_from_csv(path).unwrap(),
+        );
+
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].timestamp, "2023.03.27T11:00:00");
+        assert_eq!((data[0].open, data[0].close), (1757.4, 1760.1));
+        assert_eq!(data[1].volume, 1497.0);
+    }
+
+    #[test]
+    fn shift_timestamp_rolls_date_and_keeps_layout() {
+        assert_eq!(shift_timestamp("2023.03.27T23:00:00", 2).as_deref(), Some("2023.03.28T01:00:00"));
+        assert_eq!(shift_timestamp("2024-01-01 00:30", -1).as_deref(), Some("2023-12-31 23:30"));
+    }
+
+    #[test]
+    fn fetch_from_csv_ignores_trailing_delimiter() {
+        let data = with_file(
+            "trailing_comma.csv",
+            b"<DATE>,<TIME>,<OPEN>,<HIGH>,<LOW>,<CLOSE>,<TICKVOL>,<VOL>,<SPREAD>\n\
+              2023.03.27,11:00:00,1757.4,1761.1,1755.7,1760.1,799,0,6,\n\
+              2023.03.27,12:00:00,1760.2,1766.2,1759.8,1764.9,1497,0,6,\n\
+              2023.03.27,13:00:00,1764.8,1772.3,1764.6,1768.2,1797,0,6,\n",
+            |path| DataEngine::new().fetch_from_csv(path).unwrap(),
+        );
+
+        let last = data.last().unwrap();
+        assert_eq!(last.timestamp, "2023.03.27T13:00:00");
+        assert_eq!(last.volume, 1797.0);
+    }
+
+    #[test]
+    fn header_positions_rejects_duplicate_names() {
+        let headers = StringRecord::from(vec!["Date", "Time", "Open", "High", "Low", "Close", "Volume", "volume"]);
+        let err = header_positions(&headers).unwrap_err();
+        assert!(err.to_string().contains("volume"));
+
+        let headers = StringRecord::from(vec!["Date", "Time", "Open", "High", "Low", "Close", "Volume", ""]);
+        let positions = header_positions(&headers).unwrap();
+        assert_eq!(positions.len(), 7);
+        assert_eq!(positions["volume"], 6);
+    }
+
+    #[test]
+    fn parse_ts_to_naive_rolls_24_00_to_next_midnight() {
+        assert_eq!(parse_ts_to_naive("2024-01-01T24:00:00"), parse_ts_to_naive("2024-01-02T00:00:00"));
+        assert_eq!(parse_ts_to_naive("2023.12.31T24:00:00"), parse_ts_to_naive("2024-01-01 00:00:00"));
+    }
+
+    #[test]
+    fn dedup_by_timestamp_keeps_the_last_of_a_repeated_bar() {
+        let mut data = vec![
+            bar("2024-06-01T09:05:00", 101.0),
+            bar("2024-06-01T09:00:00", 102.0),
+            bar("2024-06-01T09:05:00", 103.0),
+        ];
+        assert_eq!(dedup_by_timestamp(&mut data), 1);
+        let closes: Vec<f64> = data.iter().map(|r| r.close).collect();
+        assert_eq!(closes, vec![102.0, 103.0]);
+    }
+
+    #[test]
+    fn write_csv_selected_emits_requested_columns_in_order() {
+        let rows = vec![bar("2024-06-01T09:00:00", 104.0)];
+        let contents = written("selected.csv", |path| write_csv_selected(&rows, path, &["close", "timestamp"]));
+        assert_eq!(contents, "close,timestamp\n104.000000,2024-06-01T09:00:00\n");
+
+        let path = temp_path("selected_unknown.csv");
+        assert!(write_csv_selected(&rows, path.to_str().unwrap(), &["close", "vwap"]).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn write_csv_with_precision_overrides_decimals() {
+        let rows = vec![bar("2024-06-01T09:00:00", 104.123456789)];
+        let contents = written("precision.csv", |path| write_csv_with_precision(&rows, path, 2));
+        assert_eq!(contents.lines().nth(1), Some("2024-06-01T09:00:00,100.00,105.00,99.50,104.12,1500.00"));
+        assert_eq!(rows[0].record(), rows[0].record_with_precision(6));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn write_parquet_schema_follows_serde_fields() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        use arrow_schema::{DataType, Schema};
+
+        fn read_back<T: Serialize>(rows: &[T], name: &str) -> (std::sync::Arc<Schema>, usize) {
+            let path = temp_path(name);
+            write_parquet(rows, path.to_str().unwrap()).unwrap();
+            let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+            let schema = builder.schema().clone();
+            let rows = builder.build().unwrap().map(|b| b.unwrap().num_rows()).sum();
+            std::fs::remove_file(&path).ok();
+            (schema, rows)
+        }
+
+        let (schema, rows) = read_back(&[bar("2024-06-01T09:00:00", 104.0)], "bars.parquet");
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, MarketData::headers());
+        assert_eq!(schema.field(0).data_type(), &DataType::Utf8);
+        assert_eq!(schema.field(1).data_type(), &DataType::Float64);
+        assert_eq!(rows, 1);
+
+        // Whole-number floats stay Float64, counts are Int64 and an all-None option is a nullable Float64.
+        #[derive(Serialize)]
+        struct Row { price: f64, count: usize, value: Option<f64> }
+        let (schema, _) = read_back(&[Row { price: 2.0, count: 3, value: None }], "typed.parquet");
+        assert_eq!(schema.field(0).data_type(), &DataType::Float64);
+        assert_eq!(schema.field(1).data_type(), &DataType::Int64);
+        assert_eq!(schema.field(2).data_type(), &DataType::Float64);
+        assert!(schema.field(2).is_nullable());
+    }
+
+    #[test]
+    fn float_format_shortest_and_scientific_keep_tiny_values() {
+        assert_eq!(FloatFormat::default().format(0.00000012), "0.000000");
+        assert_eq!(FloatFormat::Shortest.format(0.00000012), "0.00000012");
+        assert_eq!(FloatFormat::Scientific.format(0.00000012), "1.2e-7");
+        assert_eq!(FloatFormat::Shortest.format(0.00000012).parse::<f64>().unwrap(), 0.00000012);
+    }
+}
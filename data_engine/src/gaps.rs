@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use crate::week_day_data::PeriodAgg;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GapFill {
+    pub date: String,
+    pub gap: f64, // open - previous close
+    pub filled: bool,
+}
+
+/// Opening gap of each day against the previous day's close, and whether the day's
+/// range traded back through that close. `daily` must be date-sorted; the first day
+/// has no predecessor and is skipped.
+pub fn gap_fills(daily: &[PeriodAgg]) -> Vec<GapFill> {
+    daily.windows(2)
+        .map(|pair| {
+            let (prev, cur) = (&pair[0], &pair[1]);
+            GapFill {
+                date: cur.date.clone(),
+                gap: cur.open - prev.close,
+                filled: cur.low <= prev.close && prev.close <= cur.high,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GapDirection {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GapEvent {
+    pub date: String,
+    pub prev_close: f64,
+    pub open: f64,
+    pub gap: f64, // open - previous close
+    pub direction: GapDirection,
+}
+
+/// Days whose open sits at least `min_gap_pct` (a fraction, e.g. 0.005) away from the
+/// previous day's close. `daily` must be date-sorted; days after a zero close are skipped.
+pub fn detect_gaps(daily: &[PeriodAgg], min_gap_pct: f64) -> Vec<GapEvent> {
+    daily.windows(2)
+        .filter_map(|pair| {
+            let (prev, cur) = (&pair[0], &pair[1]);
+            let gap = cur.open - prev.close;
+            if prev.close == 0.0 || gap == 0.0 || gap.abs() / prev.close.abs() < min_gap_pct {
+                return None;
+            }
+            Some(GapEvent {
+                date: cur.date.clone(),
+                prev_close: prev.close,
+                open: cur.open,
+                gap,
+                direction: if gap > 0.0 { GapDirection::Up } else { GapDirection::Down },
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,341 @@
+use crate::candle_type::DEFAULT_EPS;
+use serde::{Deserialize, Serialize};
+
+use crate::data_engine::{CsvRecord, FloatFormat, MarketData};
+use crate::session_type::{Session, SessionConfig};
+use crate::week_day_data::PeriodAgg;
+
+/// `(x - mean) / std` of each value against the trailing `window` values (itself
+/// included), using the population standard deviation. `None` during warm-up and
+/// when the window is flat.
+pub fn rolling_zscore(values: &[f64], window: usize) -> Vec<Option<f64>> {
+    if window == 0 {
+        return vec![None; values.len()];
+    }
+
+    (0..values.len()).map(|i| {
+        if i + 1 < window {
+            return None;
+        }
+        let slice = &values[i + 1 - window..=i];
+        let mean = slice.iter().sum::<f64>() / window as f64;
+        let var = slice.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / window as f64;
+        let std = var.sqrt();
+        if std < DEFAULT_EPS { None } else { Some((values[i] - mean) / std) }
+    }).collect()
+}
+
+pub fn zscore_close(daily: &[PeriodAgg], window: usize) -> Vec<Option<f64>> {
+    let closes: Vec<f64> = daily.iter().map(|d| d.close).collect();
+    rolling_zscore(&closes, window)
+}
+
+/// Where the close sits within the bar's range: 0.0 at the low, 1.0 at the high.
+/// `None` for zero-range bars.
+pub fn close_location(high: f64, low: f64, close: f64) -> Option<f64> {
+    let range = high - low;
+    if range < DEFAULT_EPS { None } else { Some((close - low) / range) }
+}
+
+/// Fraction of days in the trailing `window` that closed in the top quarter of their
+/// range. Zero-range days are left out of both counts; `None` during warm-up or when
+/// every day in the window is zero-range.
+pub fn strong_close_ratio(daily: &[PeriodAgg], window: usize) -> Vec<Option<f64>> {
+    if window == 0 {
+        return vec![None; daily.len()];
+    }
+    let locations: Vec<Option<f64>> = daily.iter()
+        .map(|d| close_location(d.high, d.low, d.close))
+        .collect();
+
+    (0..daily.len()).map(|i| {
+        if i + 1 < window {
+            return None;
+        }
+        let valid: Vec<f64> = locations[i + 1 - window..=i].iter().flatten().copied().collect();
+        if valid.is_empty() {
+            return None;
+        }
+        let strong = valid.iter().filter(|clv| **clv >= 0.75).count();
+        Some(strong as f64 / valid.len() as f64)
+    }).collect()
+}
+
+/// Typical price `(high + low + close) / 3` per bar.
+pub fn typical_prices(data: &[MarketData]) -> Vec<f64> {
+    data.iter().map(|r| typical_price(r.high, r.low, r.close)).collect()
+}
+
+pub fn typical_prices_periods(aggs: &[PeriodAgg]) -> Vec<f64> {
+    aggs.iter().map(|a| typical_price(a.high, a.low, a.close)).collect()
+}
+
+/// Weighted close `(high + low + 2 * close) / 4` per bar.
+pub fn weighted_close(data: &[MarketData]) -> Vec<f64> {
+    data.iter().map(|r| (r.high + r.low + 2.0 * r.close) / 4.0).collect()
+}
+
+pub fn weighted_close_periods(aggs: &[PeriodAgg]) -> Vec<f64> {
+    aggs.iter().map(|a| (a.high + a.low + 2.0 * a.close) / 4.0).collect()
+}
+
+/// Indices of bars whose volume exceeds `multiplier` times the mean volume of the
+/// `window` bars before it. The first `window` bars have no full lookback and are
+/// never flagged.
+pub fn volume_spikes(data: &[MarketData], window: usize, multiplier: f64) -> Vec<usize> {
+    if window == 0 {
+        return Vec::new();
+    }
+
+    let mut spikes = Vec::new();
+    let mut sum: f64 = data.iter().take(window).map(|r| r.volume).sum();
+    for i in window..data.len() {
+        let avg = sum / window as f64;
+        if data[i].volume > multiplier * avg {
+            spikes.push(i);
+        }
+        sum += data[i].volume - data[i - window].volume;
+    }
+    spikes
+}
+
+pub fn typical_price(high: f64, low: f64, close: f64) -> f64 {
+    (high + low + close) / 3.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Regime {
+    Low,
+    Medium,
+    High,
+}
+
+impl Regime {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Regime::Low => "Low",
+            Regime::Medium => "Medium",
+            Regime::High => "High",
+        }
+    }
+}
+
+/// Tercile of each day's `high - low` within the trailing `window` days (itself
+/// included, ties counted half). `None` during warm-up.
+pub fn volatility_regime(daily: &[PeriodAgg], window: usize) -> Vec<Option<Regime>> {
+    if window == 0 {
+        return vec![None; daily.len()];
+    }
+    let ranges: Vec<f64> = daily.iter().map(|d| d.high - d.low).collect();
+
+    (0..ranges.len()).map(|i| {
+        if i + 1 < window {
+            return None;
+        }
+        let slice = &ranges[i + 1 - window..=i];
+        let below = slice.iter().filter(|r| **r < ranges[i]).count() as f64;
+        let equal = slice.iter().filter(|r| **r == ranges[i]).count() as f64;
+        let pct = (below + 0.5 * equal) / window as f64;
+        Some(if pct < 1.0 / 3.0 {
+            Regime::Low
+        } else if pct < 2.0 / 3.0 {
+            Regime::Medium
+        } else {
+            Regime::High
+        })
+    }).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VwapBands {
+    pub vwap: f64,
+    pub std: f64,
+    pub upper: f64,
+    pub lower: f64,
+}
+
+/// Volume-weighted average typical price of the bars in `session`, with bands
+/// `n_std` volume-weighted standard deviations either side. Pass one day's bars to
+/// anchor on that day's session. `None` when the session has no volume.
+pub fn session_vwap_bands(bars: &[MarketData], session: Session, cfg: &SessionConfig, n_std: f64) -> Option<VwapBands> {
+    let in_session: Vec<(f64, f64)> = bars.iter()
+        .filter(|r| Session::from_timestamp_with_config(&r.timestamp, cfg) == session)
+        .map(|r| (typical_price(r.high, r.low, r.close), r.volume))
+        .collect();
+
+    let total_volume: f64 = in_session.iter().map(|(_, v)| v).sum();
+    if total_volume <= 0.0 {
+        return None;
+    }
+    let vwap = in_session.iter().map(|(p, v)| p * v).sum::<f64>() / total_volume;
+    let var = in_session.iter().map(|(p, v)| v * (p - vwap).powi(2)).sum::<f64>() / total_volume;
+    let std = var.sqrt();
+
+    Some(VwapBands { vwap, std, upper: vwap + n_std * std, lower: vwap - n_std * std })
+}
+
+/// Mean `close` over the trailing `window` days (itself included). `None` for the
+/// first `window - 1` days. `aggs` must be in date order.
+pub fn sma(aggs: &[PeriodAgg], window: usize) -> Vec<Option<f64>> {
+    if window == 0 {
+        return vec![None; aggs.len()];
+    }
+    let mut out = Vec::with_capacity(aggs.len());
+    let mut sum = 0.0;
+    for (i, agg) in aggs.iter().enumerate() {
+        sum += agg.close;
+        if i >= window {
+            sum -= aggs[i - window].close;
+        }
+        out.push(if i + 1 >= window { Some(sum / window as f64) } else { None });
+    }
+    out
+}
+
+/// Exponential moving average of `close` with smoothing `2 / (period + 1)`, seeded
+/// with the simple average of the first `period` closes. `None` before the seed.
+pub fn ema(aggs: &[PeriodAgg], period: usize) -> Vec<Option<f64>> {
+    let mut out = vec![None; aggs.len()];
+    if period == 0 || aggs.len() < period {
+        return out;
+    }
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut value = aggs[..period].iter().map(|a| a.close).sum::<f64>() / period as f64;
+    out[period - 1] = Some(value);
+    for (i, agg) in aggs.iter().enumerate().skip(period) {
+        value += alpha * (agg.close - value);
+        out[i] = Some(value);
+    }
+    out
+}
+
+/// True range of each day: `max(high - low, |high - prev_close|, |low - prev_close|)`,
+/// just `high - low` on the first day.
+pub fn true_ranges(aggs: &[PeriodAgg]) -> Vec<f64> {
+    aggs.iter().enumerate().map(|(i, agg)| {
+        let range = agg.high - agg.low;
+        match i.checked_sub(1).map(|p| aggs[p].close) {
+            Some(prev_close) => range.max((agg.high - prev_close).abs()).max((agg.low - prev_close).abs()),
+            None => range,
+        }
+    }).collect()
+}
+
+/// Wilder-smoothed average true range: the mean of the first `period` true ranges,
+/// then `(prev * (period - 1) + tr) / period`. `None` until `period` days are in.
+pub fn atr(aggs: &[PeriodAgg], period: usize) -> Vec<Option<f64>> {
+    let mut out = vec![None; aggs.len()];
+    if period == 0 || aggs.len() < period {
+        return out;
+    }
+    let tr = true_ranges(aggs);
+    let n = period as f64;
+    let mut value = tr[..period].iter().sum::<f64>() / n;
+    out[period - 1] = Some(value);
+    for i in period..tr.len() {
+        value = (value * (n - 1.0) + tr[i]) / n;
+        out[i] = Some(value);
+    }
+    out
+}
+
+/// Wilder RSI of `close` in 0..=100. Average gain and loss are seeded with the plain
+/// mean of the first `period` changes, then smoothed like `atr`. `None` for the first
+/// `period` days; 100.0 when there were no losses (50.0 if prices never moved).
+pub fn rsi(aggs: &[PeriodAgg], period: usize) -> Vec<Option<f64>> {
+    let mut out = vec![None; aggs.len()];
+    if period == 0 || aggs.len() <= period {
+        return out;
+    }
+    let changes: Vec<f64> = aggs.windows(2).map(|pair| pair[1].close - pair[0].close).collect();
+    let n = period as f64;
+    let value = |gain: f64, loss: f64| {
+        if loss == 0.0 {
+            if gain == 0.0 { 50.0 } else { 100.0 }
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    };
+
+    let mut avg_gain = changes[..period].iter().map(|c| c.max(0.0)).sum::<f64>() / n;
+    let mut avg_loss = changes[..period].iter().map(|c| (-c).max(0.0)).sum::<f64>() / n;
+    out[period] = Some(value(avg_gain, avg_loss));
+
+    for (i, change) in changes.iter().enumerate().skip(period) {
+        avg_gain = (avg_gain * (n - 1.0) + change.max(0.0)) / n;
+        avg_loss = (avg_loss * (n - 1.0) + (-change).max(0.0)) / n;
+        out[i + 1] = Some(value(avg_gain, avg_loss));
+    }
+    out
+}
+
+/// Bollinger Bands of `close` as `(lower, middle, upper)`: `middle` is the `sma` over
+/// `period` days and the bands sit `num_std` standard deviations either side. The
+/// deviation is the population one (divided by `period`, not `period - 1`), the usual
+/// convention for Bollinger Bands. `None` during warm-up.
+pub fn bollinger(aggs: &[PeriodAgg], period: usize, num_std: f64) -> Vec<Option<(f64, f64, f64)>> {
+    sma(aggs, period).into_iter().enumerate().map(|(i, middle)| {
+        let middle = middle?;
+        let var = aggs[i + 1 - period..=i].iter()
+            .map(|a| (a.close - middle).powi(2))
+            .sum::<f64>() / period as f64;
+        let width = num_std * var.sqrt();
+        Some((middle - width, middle, middle + width))
+    }).collect()
+}
+
+/// Classic floor-trader pivot levels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pivots {
+    pub pp: f64,
+    pub r1: f64,
+    pub r2: f64,
+    pub r3: f64,
+    pub s1: f64,
+    pub s2: f64,
+    pub s3: f64,
+}
+
+impl CsvRecord for Pivots {
+    fn headers() -> &'static [&'static str] {
+        &["pp", "r1", "r2", "r3", "s1", "s2", "s3"]
+    }
+
+    fn record(&self) -> Vec<String> {
+        self.record_fmt(FloatFormat::default())
+    }
+
+    fn record_fmt(&self, fmt: FloatFormat) -> Vec<String> {
+        [self.pp, self.r1, self.r2, self.r3, self.s1, self.s2, self.s3]
+            .iter()
+            .map(|v| fmt.format(*v))
+            .collect()
+    }
+}
+
+/// Pivots for the day after `agg`, from its high, low and close: `pp` is the typical
+/// price, `r1/s1 = 2pp - low/high`, `r2/s2 = pp ± range`, `r3 = high + 2(pp - low)`
+/// and `s3 = low - 2(high - pp)`.
+pub fn pivot_points(agg: &PeriodAgg) -> Pivots {
+    let (high, low) = (agg.high, agg.low);
+    let pp = typical_price(high, low, agg.close);
+    let range = high - low;
+    Pivots {
+        pp,
+        r1: 2.0 * pp - low,
+        r2: pp + range,
+        r3: high + 2.0 * (pp - low),
+        s1: 2.0 * pp - high,
+        s2: pp - range,
+        s3: low - 2.0 * (high - pp),
+    }
+}
+
+/// Each day's pivots from the day before it; `None` for the first day. `aggs` must be
+/// in date order.
+pub fn pivot_points_series(aggs: &[PeriodAgg]) -> Vec<Option<Pivots>> {
+    std::iter::once(None)
+        .chain(aggs.windows(2).map(|pair| Some(pivot_points(&pair[0]))))
+        .take(aggs.len())
+        .collect()
+}
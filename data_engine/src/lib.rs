@@ -4,7 +4,17 @@ pub mod session_type;
 pub mod session_data_agg;
 pub mod week_day_data;
 pub mod weekly_aggregator;
+pub mod weekly_table_aggregator;
+pub mod monthly_table_aggregator;
 pub mod daily_session_aggregator;
+pub mod session_stats;
+pub mod session_path;
+pub mod daily_summary;
+pub mod bar_relation;
+pub mod resample;
+pub mod indicators;
+pub mod gaps;
+pub mod pipeline;
 
 // re-exports for simple upstream use
 // pub use data_engine::{DataEngine, write_csv, MarketData};
@@ -1,5 +1,6 @@
 use std::error::Error;
-use std::path::Path;
+use std::path::PathBuf;
+use std::process;
 
 pub mod data_engine;
 pub mod candle_type;
@@ -7,33 +8,152 @@ pub mod session_type;
 pub mod session_data_agg;
 pub mod week_day_data;
 pub mod weekly_table_aggregator;
+pub mod monthly_table_aggregator;
 pub mod daily_session_aggregator;
+pub mod session_stats;
+pub mod session_path;
+pub mod daily_summary;
+pub mod bar_relation;
+pub mod resample;
+pub mod indicators;
+pub mod gaps;
+pub mod pipeline;
 
-use crate::data_engine::{DataEngine, write_csv};
-use crate::week_day_data::aggregate_periods;
-use crate::weekly_table_aggregator::aggregate_weekly_table;
-use crate::session_data_agg::aggregate_sessions;
-use crate::daily_session_aggregator::aggregate_daily_session_table;
+use crate::data_engine::{AggregateOptions, DataEngine, write_csv};
+use crate::pipeline::run_pipeline;
+use crate::session_data_agg::write_sessions_csv;
+use crate::session_type::Session;
+
+// Where the binary read from before it took arguments; still the default input.
+const DEFAULT_INPUT: &str = "/home/daredevil/Development/Dev/Learn/trading_system/US2000.csv";
+
+fn usage() -> String {
+    format!("\
+Usage: data_engine [INPUT] [OUTPUT_DIR] [--daily] [--weekly] [--daily-sessions]
+                   [--session-aggregates] [--summary] [--midnight-session SESSION]
+
+  INPUT                 CSV of bars to load
+                        (default: {})
+  OUTPUT_DIR            Where the aggregate CSVs are written (default: current directory)
+  --daily               Write daily_aggregates.csv
+  --weekly              Write weekly_table_aggregates.csv
+  --daily-sessions      Write daily_session_table_aggregates.csv, one row per day
+  --session-aggregates  Also write the raw per-session rows to session_aggregates.csv
+  --summary             Also write the wide per-day view to daily_summary.csv
+  --midnight-session SESSION
+                        Session (AS, LN, NYAM, NYL or NYPM) for bars stamped 00:00
+                        or 24:00; by default they belong to no session
+
+Without --daily, --weekly or --daily-sessions all three tables are written, as the
+binary did before it took arguments.", DEFAULT_INPUT)
+}
+
+struct Args {
+    input: PathBuf,
+    out_dir: PathBuf,
+    daily: bool,
+    weekly: bool,
+    daily_sessions: bool,
+    session_aggregates: bool,
+    summary: bool,
+    midnight_session: Session,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut positional = Vec::new();
+        let (mut daily, mut weekly, mut daily_sessions) = (false, false, false);
+        let (mut session_aggregates, mut summary) = (false, false);
+        let mut midnight_session = Session::Unknown;
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--daily" => daily = true,
+                "--weekly" => weekly = true,
+                "--daily-sessions" => daily_sessions = true,
+                "--session-aggregates" => session_aggregates = true,
+                "--summary" => summary = true,
+                "--midnight-session" => {
+                    let label = args.next().ok_or_else(|| format!("--midnight-session needs a session\n\n{}", usage()))?;
+                    midnight_session = Session::from_label(&label)
+                        .ok_or_else(|| format!("unknown session {}\n\n{}", label, usage()))?;
+                }
+                "-h" | "--help" => return Err(usage()),
+                flag if flag.starts_with("--") => return Err(format!("unknown option {}\n\n{}", flag, usage())),
+                _ => positional.push(arg),
+            }
+        }
+        if positional.len() > 2 {
+            return Err(format!("too many arguments\n\n{}", usage()));
+        }
+        let mut positional = positional.into_iter();
+
+        // No table flags means the original three tables.
+        let all = !(daily || weekly || daily_sessions);
+        Ok(Args {
+            input: positional.next().map(PathBuf::from).unwrap_or_else(|| PathBuf::from(DEFAULT_INPUT)),
+            out_dir: positional.next().map(PathBuf::from).unwrap_or_else(|| PathBuf::from(".")),
+            daily: daily || all,
+            weekly: weekly || all,
+            daily_sessions: daily_sessions || all,
+            session_aggregates,
+            summary,
+            midnight_session,
+        })
+    }
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let csv_path = Path::new("/home/daredevil/Development/Dev/Learn/trading_system/US2000.csv");
-  
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+    if !args.input.is_file() {
+        eprintln!("Input file not found: {}\n\n{}", args.input.display(), usage());
+        process::exit(1);
+    }
+    let out = |name: &str| args.out_dir.join(name).to_string_lossy().into_owned();
+
     let engine = DataEngine::new();
-    let data = engine.fetch_from_csv(csv_path)?;
+    let data = engine.fetch_from_csv(&args.input)?;
     println!("Loaded {} rows", data.len());
 
-    let (daily, _, _, _, _) = aggregate_periods(&data);
-    write_csv(&daily, "daily_aggregates.csv").expect("Failed to write daily aggregates CSV");
-    println!("Daily aggregates written to daily_aggregates.csv");
+    let mut opts = AggregateOptions::default();
+    opts.session_config.midnight_session = args.midnight_session;
+    let result = run_pipeline(&data, &opts);
+
+    if args.daily {
+        let path = out("daily_aggregates.csv");
+        write_csv(&result.daily, &path).expect("Failed to write daily aggregates CSV");
+        println!("Daily aggregates written to {}", path);
+    }
+
+    if args.weekly {
+        let path = out("weekly_table_aggregates.csv");
+        write_csv(&result.weekly_table, &path).expect("Failed to write weekly table aggregates CSV");
+        println!("Weekly table aggregates written to {}", path);
+    }
+
+    if args.daily_sessions {
+        let path = out("daily_session_table_aggregates.csv");
+        write_csv(&result.daily_session_table, &path).expect("Failed to write daily session table aggregates CSV");
+        println!("Daily session table aggregates written to {}", path);
+    }
+
+    if args.session_aggregates {
+        let path = out("session_aggregates.csv");
+        write_sessions_csv(&result.sessions, &path).expect("Failed to write session aggregates CSV");
+        println!("Session aggregates written to {}", path);
+    }
 
-    let weekly_table_aggs = aggregate_weekly_table(&daily);
-    write_csv(&weekly_table_aggs, "weekly_table_aggregates.csv").expect("Failed to write weekly table aggregates CSV");
-    println!("Weekly table aggregates written to weekly_table_aggregates.csv");
+    if args.summary {
+        let path = out("daily_summary.csv");
+        write_csv(&result.daily_summary, &path).expect("Failed to write daily summary CSV");
+        println!("Daily summary written to {}", path);
+    }
 
-    let session_aggs = aggregate_sessions(&data);
-    let daily_session_table_aggs = aggregate_daily_session_table(&session_aggs);
-    write_csv(&daily_session_table_aggs, "daily_session_table_aggregates.csv").expect("Failed to write daily session table aggregates CSV");
-    println!("Daily session table aggregates written to daily_session_table_aggregates.csv");
-    
     Ok(())
-}
\ No newline at end of file
+}
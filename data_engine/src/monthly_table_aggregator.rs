@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::data_engine::{AggregateOptions, CsvRecord, FloatFormat, parse_ts_to_naive};
+use crate::candle_type::pattern_from_ohlc_cfg;
+use crate::week_day_data::PeriodAgg;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonthlyTableAgg {
+    pub year: String,
+    pub month: String,
+    // Pattern of each ISO week's days within the month, in order; empty when absent
+    pub week1_pattern: String,
+    pub week2_pattern: String,
+    pub week3_pattern: String,
+    pub week4_pattern: String,
+    pub week5_pattern: String,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub high_week: String,
+    pub low_week: String,
+    pub month_pattern: String,
+}
+
+impl CsvRecord for MonthlyTableAgg {
+    fn headers() -> &'static [&'static str] {
+        &[
+            "Year", "Month", "Week1", "Week2", "Week3", "Week4", "Week5",
+            "Open", "High", "Low", "Close", "Volume", "HighWeek", "LowWeek", "MonthPattern",
+        ]
+    }
+
+    fn record(&self) -> Vec<String> {
+        self.record_fmt(FloatFormat::default())
+    }
+
+    fn record_fmt(&self, fmt: FloatFormat) -> Vec<String> {
+        vec![
+            self.year.clone(),
+            self.month.clone(),
+            self.week1_pattern.clone(),
+            self.week2_pattern.clone(),
+            self.week3_pattern.clone(),
+            self.week4_pattern.clone(),
+            self.week5_pattern.clone(),
+            fmt.format(self.open),
+            fmt.format(self.high),
+            fmt.format(self.low),
+            fmt.format(self.close),
+            fmt.format(self.volume),
+            self.high_week.clone(),
+            self.low_week.clone(),
+            self.month_pattern.clone(),
+        ]
+    }
+}
+
+const WEEKS_PER_MONTH: usize = 5;
+
+/// One row per `YYYY-MM`, in chronological order. Each day goes to the month's Nth
+/// ISO week that has data (a sixth week, only possible with weekend bars, folds into
+/// Week5); a week's pattern is classified from its days inside the month. Rows whose
+/// date doesn't parse are skipped.
+pub fn aggregate_monthly_table(daily_aggs: &[PeriodAgg]) -> Vec<MonthlyTableAgg> {
+    aggregate_monthly_table_with(daily_aggs, &AggregateOptions::default())
+}
+
+/// `aggregate_monthly_table` with week and month patterns classified by `opts.candle_config`.
+pub fn aggregate_monthly_table_with(daily_aggs: &[PeriodAgg], opts: &AggregateOptions) -> Vec<MonthlyTableAgg> {
+    let mut monthly_map: BTreeMap<String, Vec<(NaiveDate, &PeriodAgg)>> = BTreeMap::new();
+
+    for d_agg in daily_aggs {
+        let date = match parse_ts_to_naive(&d_agg.date) {
+            Some(dt) => dt.date(),
+            None => continue,
+        };
+        monthly_map.entry(date.format("%Y-%m").to_string())
+            .or_default()
+            .push((date, d_agg));
+    }
+
+    let mut result = Vec::new();
+
+    for mut days in monthly_map.into_values() {
+        days.sort_by_key(|(date, _)| *date);
+        let first_day = days[0].0;
+
+        // (open, high, low, close) of each week bucket
+        let mut weeks: Vec<(f64, f64, f64, f64)> = Vec::new();
+        let mut last_week = None;
+        let mut volume = 0.0;
+
+        for (date, day) in &days {
+            let iso = date.iso_week();
+            if last_week != Some(iso) && weeks.len() < WEEKS_PER_MONTH {
+                weeks.push((day.open, day.high, day.low, day.close));
+            } else {
+                let week = weeks.last_mut().unwrap();
+                week.1 = week.1.max(day.high);
+                week.2 = week.2.min(day.low);
+                week.3 = day.close;
+            }
+            last_week = Some(iso);
+            volume += day.volume;
+        }
+
+        let open = days[0].1.open;
+        let close = days[days.len() - 1].1.close;
+        let (mut high, mut low) = (f64::MIN, f64::MAX);
+        let (mut high_week, mut low_week) = (0, 0);
+        for (i, week) in weeks.iter().enumerate() {
+            if week.1 > high {
+                high = week.1;
+                high_week = i;
+            }
+            if week.2 < low {
+                low = week.2;
+                low_week = i;
+            }
+        }
+
+        let week_pattern = |i: usize| {
+            weeks.get(i)
+                .map(|&(o, h, l, c)| pattern_from_ohlc_cfg(o, h, l, c, &opts.candle_config))
+                .unwrap_or_default()
+        };
+
+        result.push(MonthlyTableAgg {
+            year: first_day.year().to_string(),
+            month: format!("{:02}", first_day.month()),
+            week1_pattern: week_pattern(0),
+            week2_pattern: week_pattern(1),
+            week3_pattern: week_pattern(2),
+            week4_pattern: week_pattern(3),
+            week5_pattern: week_pattern(4),
+            open,
+            high,
+            low,
+            close,
+            volume,
+            high_week: format!("Week{}", high_week + 1),
+            low_week: format!("Week{}", low_week + 1),
+            month_pattern: pattern_from_ohlc_cfg(open, high, low, close, &opts.candle_config),
+        });
+    }
+
+    result
+}
@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::io::Write;
+use csv::{Writer, WriterBuilder};
+
+use crate::daily_session_aggregator::{aggregate_daily_session_table_with, DailySessionTableAgg};
+use crate::daily_summary::{build_daily_summary, DailySummary};
+use crate::data_engine::{AggregateOptions, CsvRecord, MarketData};
+use crate::session_data_agg::{aggregate_sessions_with, SessionAgg};
+use crate::week_day_data::{aggregate_periods_with, PeriodAgg};
+use crate::weekly_table_aggregator::{aggregate_weekly_table_with, WeeklyTableAgg};
+
+/// Every table the binary produces, computed from one set of bars.
+#[derive(Debug, Clone)]
+pub struct PipelineResult {
+    pub daily: Vec<PeriodAgg>,
+    pub weekly_table: Vec<WeeklyTableAgg>,
+    pub sessions: Vec<SessionAgg>,
+    pub daily_session_table: Vec<DailySessionTableAgg>,
+    pub daily_summary: Vec<DailySummary>,
+}
+
+pub fn run_pipeline(data: &[MarketData], opts: &AggregateOptions) -> PipelineResult {
+    let (daily, _, _, _, _) = aggregate_periods_with(data, opts);
+    let weekly_table = aggregate_weekly_table_with(&daily, opts);
+    let sessions = aggregate_sessions_with(data, opts);
+    let daily_session_table = aggregate_daily_session_table_with(&sessions, opts);
+    let daily_summary = build_daily_summary(&daily, &daily_session_table);
+
+    PipelineResult { daily, weekly_table, sessions, daily_session_table, daily_summary }
+}
+
+/// Write every table in long format: one `date, level, field, value` row per cell.
+/// Session fields are prefixed with the session (`NYAM.open`); weekly rows are dated
+/// `"<Year> <Week>"`.
+pub fn write_tidy(result: &PipelineResult, path: &str) -> Result<(), Box<dyn Error>> {
+    let mut writer = WriterBuilder::new().from_path(path)?;
+    writer.write_record(["date", "level", "field", "value"])?;
+
+    write_level(&mut writer, "daily", &result.daily, &["date"], |d| (d.date.clone(), String::new()))?;
+    write_level(&mut writer, "weekly", &result.weekly_table, &["Year", "Week"], |w| {
+        (format!("{} {}", w.year, w.week), String::new())
+    })?;
+    write_level(&mut writer, "session", &result.sessions, &["date", "session"], |s| {
+        (s.date.clone(), format!("{}.", s.session))
+    })?;
+    write_level(&mut writer, "daily_session", &result.daily_session_table, &["Date"], |d| (d.date.clone(), String::new()))?;
+    write_level(&mut writer, "summary", &result.daily_summary, &["Date"], |d| (d.date.clone(), String::new()))?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+// Unpivot `rows` through `headers()`/`record()`. `key` gives each row's date and field
+// prefix; the `key_columns` it was built from are not repeated as fields.
+fn write_level<T: CsvRecord, W: Write>(
+    writer: &mut Writer<W>,
+    level: &str,
+    rows: &[T],
+    key_columns: &[&str],
+    key: impl Fn(&T) -> (String, String),
+) -> Result<(), Box<dyn Error>> {
+    for row in rows {
+        let (date, prefix) = key(row);
+        for (field, value) in T::headers().iter().zip(row.record()) {
+            if key_columns.contains(field) { continue; }
+            writer.write_record([date.as_str(), level, &format!("{}{}", prefix, field), &value])?;
+        }
+    }
+    Ok(())
+}
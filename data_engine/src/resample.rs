@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use chrono::{Datelike, Duration, NaiveDateTime, Timelike, Weekday};
+use crate::data_engine::{format_like, parse_ts_to_naive, MarketData};
+
+// Fold `bar` into `bucket` with the usual OHLCV merge.
+fn merge_bar(bucket: &mut MarketData, bar: &MarketData) {
+    bucket.high = bucket.high.max(bar.high);
+    bucket.low = bucket.low.min(bar.low);
+    bucket.close = bar.close;
+    bucket.volume += bar.volume;
+}
+
+/// Volume bars: consecutive bars are merged until their summed volume reaches
+/// `volume_per_bar`, then a bar is emitted with the bucket's first timestamp.
+/// A bar that reaches the threshold on its own closes any open bucket and is emitted
+/// alone. A trailing partial bucket is still emitted so no volume is lost.
+pub fn volume_bars(data: &[MarketData], volume_per_bar: f64) -> Vec<MarketData> {
+    let mut bars: Vec<MarketData> = Vec::new();
+    let mut bucket: Option<MarketData> = None;
+
+    for r in data {
+        if r.volume >= volume_per_bar {
+            bars.extend(bucket.take());
+            bars.push(r.clone());
+            continue;
+        }
+
+        match bucket.as_mut() {
+            Some(b) => merge_bar(b, r),
+            None => bucket = Some(r.clone()),
+        }
+
+        if bucket.as_ref().is_some_and(|b| b.volume >= volume_per_bar) {
+            bars.extend(bucket.take());
+        }
+    }
+
+    bars.extend(bucket);
+    bars
+}
+
+/// Time bars of `minutes` each, aligned to midnight (buckets restart every day), each
+/// stamped with its bucket start in the input's timestamp layout. Empty buckets are
+/// not emitted, nor are bars whose timestamp doesn't parse. Open and close come from
+/// the earliest and latest bar in each bucket. `minutes == 0` yields nothing.
+pub fn resample(data: &[MarketData], minutes: u32) -> Vec<MarketData> {
+    if minutes == 0 {
+        return Vec::new();
+    }
+
+    let mut bars: Vec<(NaiveDateTime, &MarketData)> = data.iter()
+        .filter_map(|r| parse_ts_to_naive(&r.timestamp).map(|dt| (dt, r)))
+        .collect();
+    bars.sort_by_key(|(dt, _)| *dt);
+
+    let mut buckets: BTreeMap<NaiveDateTime, MarketData> = BTreeMap::new();
+    for (dt, r) in bars {
+        let since_midnight = dt.num_seconds_from_midnight() / 60;
+        let start = dt.date().and_hms_opt(0, 0, 0).unwrap()
+            + Duration::minutes((since_midnight - since_midnight % minutes) as i64);
+        match buckets.get_mut(&start) {
+            Some(bucket) => merge_bar(bucket, r),
+            None => {
+                buckets.insert(start, MarketData { timestamp: format_like(&r.timestamp, start), ..r.clone() });
+            }
+        }
+    }
+    buckets.into_values().collect()
+}
+
+/// `data` (in time order) with synthetic flat bars (every price at the previous close,
+/// zero volume) inserted every `interval_minutes` wherever consecutive bars are further
+/// apart than that. Synthetic timestamps use the layout of the bar before the gap.
+/// With `skip_weekends`, gaps that touch a Saturday or Sunday are left unfilled. Bars
+/// whose timestamp doesn't parse are passed through and never filled around.
+pub fn forward_fill(data: &[MarketData], interval_minutes: u32, skip_weekends: bool) -> Vec<MarketData> {
+    let mut out: Vec<MarketData> = Vec::with_capacity(data.len());
+    if interval_minutes == 0 {
+        out.extend_from_slice(data);
+        return out;
+    }
+    let step = Duration::minutes(interval_minutes as i64);
+    let mut prev: Option<(NaiveDateTime, &MarketData)> = None;
+
+    for r in data {
+        let dt = parse_ts_to_naive(&r.timestamp);
+        if let (Some((prev_dt, prev_bar)), Some(dt)) = (prev, dt) {
+            let weekend = prev_dt.date().iter_days()
+                .take_while(|d| *d <= dt.date())
+                .any(|d| matches!(d.weekday(), Weekday::Sat | Weekday::Sun));
+            if !(skip_weekends && weekend) {
+                let mut t = prev_dt + step;
+                while t < dt {
+                    out.push(MarketData {
+                        timestamp: format_like(&prev_bar.timestamp, t),
+                        open: prev_bar.close,
+                        high: prev_bar.close,
+                        low: prev_bar.close,
+                        close: prev_bar.close,
+                        volume: 0.0,
+                        source: prev_bar.source.clone(),
+                    });
+                    t += step;
+                }
+            }
+        }
+        if let Some(dt) = dt {
+            prev = Some((dt, r));
+        }
+        out.push(r.clone());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(ts: &str, open: f64, high: f64, low: f64, close: f64) -> MarketData {
+        MarketData { timestamp: ts.to_string(), open, high, low, close, volume: 10.0, source: None }
+    }
+
+    #[test]
+    fn resample_buckets_from_midnight_and_skips_gaps() {
+        let data = vec![
+            bar("2024.01.02T09:01:00", 100.0, 101.0, 99.0, 100.5),
+            bar("2024.01.02T09:03:00", 100.5, 103.0, 100.0, 102.0),
+            bar("2024.01.02T09:04:00", 102.0, 102.5, 98.0, 99.0),
+            bar("2024.01.02T09:21:00", 99.0, 99.5, 98.5, 99.2),
+        ];
+
+        let bars = resample(&data, 5);
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].timestamp, "2024.01.02T09:00:00");
+        assert_eq!((bars[0].open, bars[0].high, bars[0].low, bars[0].close), (100.0, 103.0, 98.0, 99.0));
+        assert_eq!(bars[0].volume, 30.0);
+        assert_eq!(bars[1].timestamp, "2024.01.02T09:20:00");
+    }
+
+    #[test]
+    fn forward_fill_inserts_flat_bars_and_can_skip_weekends() {
+        let data = vec![
+            bar("2024.01.05T09:00:00", 100.0, 101.0, 99.0, 100.5), // Friday
+            bar("2024.01.05T09:03:00", 100.5, 103.0, 100.0, 102.0),
+            bar("2024.01.08T09:00:00", 102.0, 102.5, 98.0, 99.0), // Monday
+        ];
+
+        let filled = forward_fill(&data[..2], 1, false);
+        assert_eq!(filled.len(), 4);
+        assert_eq!(filled[1].timestamp, "2024.01.05T09:01:00");
+        assert_eq!((filled[1].open, filled[1].high, filled[1].low, filled[1].close), (100.5, 100.5, 100.5, 100.5));
+        assert_eq!(filled[2].volume, 0.0);
+
+        assert_eq!(forward_fill(&data, 1, true).len(), 5);
+    }
+}
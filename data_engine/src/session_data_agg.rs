@@ -1,14 +1,12 @@
-use std::cmp::Ordering;
 use std::collections::HashMap;
-use crate::data_engine::{CsvRecord, MarketData};
-use crate::session_type::{session_from_timestamp_enum, Session};
-use serde::{Deserialize, Serialize};
-use crate::candle_type::{pattern_from_ohlc, DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG, DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS};
 use std::error::Error;
-use std::path::Path;
-use csv::WriterBuilder;
+use crate::data_engine::{parse_ts_to_naive, write_csv, AggregateOptions, CsvRecord, FloatFormat, MarketData};
+use crate::session_type::Session;
+use serde::{Deserialize, Serialize};
+use crate::candle_type::{candle_metrics, pattern_from_ohlc_cfg};
+use crate::indicators::typical_price;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SessionAgg {
     pub date: String,
     pub session: Session,
@@ -17,65 +15,239 @@ pub struct SessionAgg {
     pub low: f64,
     pub close: f64,
     pub volume: f64,
+    pub pattern: String,
+    pub open_crosses: usize, // Bars after the first whose range straddles the session open
+    // Body and wicks as fractions of the session range; 0.0 for a flat session
+    pub body_ratio: f64,
+    pub upper_wick_ratio: f64,
+    pub lower_wick_ratio: f64,
+    pub mfe: f64, // Furthest run from the open in the direction the session closed
+    pub vwap: f64, // Volume-weighted typical price; 0.0 when the session traded no volume
     pub high_ts: String, // New field to store the timestamp of the high
     pub low_ts: String, // New field to store the timestamp of the low
-    pub pattern: String,
+    pub open_ts: String, // Earliest bar of the session, whatever the input order
+    pub close_ts: String, // Latest bar of the session
 }
 
 pub fn aggregate_sessions(data: &[MarketData]) -> Vec<SessionAgg> {
-    let mut aggs: HashMap<(String, Session), SessionAgg> = HashMap::new();
+    aggregate_sessions_with(data, &AggregateOptions::default())
+}
+
+pub fn aggregate_sessions_with(data: &[MarketData], opts: &AggregateOptions) -> Vec<SessionAgg> {
+    let groups = group_sessions(data, opts);
+
+    let mut out_aggs: Vec<SessionAgg> = groups.into_iter()
+        .map(|(key, bars)| build_session(key, &bars, opts))
+        .collect();
+
+    opts.sort_order.apply(&mut out_aggs, |a, b| a.date.cmp(&b.date).then(a.session.cmp(&b.session)));
+
+    out_aggs
+}
+
+#[cfg(feature = "parallel")]
+pub fn aggregate_sessions_parallel(data: &[MarketData]) -> Vec<SessionAgg> {
+    aggregate_sessions_parallel_with(data, &AggregateOptions::default())
+}
+
+/// `aggregate_sessions_with` spread over the rayon thread pool. Chunks of `data` are
+/// grouped by `(date, session)` in parallel, merged in input order, and each session
+/// is then built in parallel, so the output matches the serial version exactly.
+#[cfg(feature = "parallel")]
+pub fn aggregate_sessions_parallel_with(data: &[MarketData], opts: &AggregateOptions) -> Vec<SessionAgg> {
+    use rayon::prelude::*;
+
+    let chunk_size = (data.len() / rayon::current_num_threads()).max(1);
+    let partials: Vec<SessionGroups> = data.par_chunks(chunk_size)
+        .map(|chunk| group_sessions(chunk, opts))
+        .collect();
+
+    let mut groups: SessionGroups = HashMap::new();
+    for partial in partials {
+        for (key, bars) in partial {
+            groups.entry(key).or_default().extend(bars);
+        }
+    }
+
+    let mut out_aggs: Vec<SessionAgg> = groups.into_par_iter()
+        .map(|(key, bars)| build_session(key, &bars, opts))
+        .collect();
+
+    opts.sort_order.apply(&mut out_aggs, |a, b| a.date.cmp(&b.date).then(a.session.cmp(&b.session)));
+
+    out_aggs
+}
+
+type SessionGroups<'a> = HashMap<(String, Session), Vec<&'a MarketData>>;
 
+// Bars of each `(date, session)` in input order; `Unknown` bars are dropped.
+fn group_sessions<'a>(data: &'a [MarketData], opts: &AggregateOptions) -> SessionGroups<'a> {
+    let mut groups: SessionGroups = HashMap::new();
     for r in data {
-        let date_part = r.timestamp.split('T').next().unwrap_or("").to_string();
-        let session = session_from_timestamp_enum(&r.timestamp);
+        let session = Session::from_timestamp_with_config(&r.timestamp, &opts.session_config);
         if session == Session::Unknown { continue; }
-        let key = (date_part.clone(), session);
-
-        aggs.entry(key)
-            .and_modify(|agg| {
-                if r.high > agg.high { 
-                    agg.high = r.high;
-                    agg.high_ts = r.timestamp.clone();
-                }
-                if r.low < agg.low { 
-                    agg.low = r.low;
-                    agg.low_ts = r.timestamp.clone();
-                }
-                agg.close = r.close;
-                agg.volume += r.volume;
-            })
-            .or_insert_with(|| SessionAgg {
-                date: date_part,
-                session,
-                open: r.open,
-                high: r.high,
-                low: r.low,
-                close: r.close,
-                volume: r.volume,
-                high_ts: r.timestamp.clone(),
-                low_ts: r.timestamp.clone(),
-                pattern: String::new(),
-            });
-    }
-
-    let mut out_aggs: Vec<SessionAgg> = aggs.into_iter().map(|(_k, mut v)| {
-        v.pattern = pattern_from_ohlc(
-            v.open, v.high, v.low, v.close,
-            DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG,
-            DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO,
-            DEFAULT_EPS,
-        );
-        v
-    }).collect();
-
-    out_aggs.sort_by(|a, b| {
-        match a.date.cmp(&b.date) {
-            Ordering::Equal => a.session.as_str().cmp(b.session.as_str()),
-            other => other,
+        groups.entry((opts.day_boundary.session_date_key(&r.timestamp, &opts.session_config), session)).or_default().push(r);
+    }
+    groups
+}
+
+fn build_session((date, session): (String, Session), bars: &[&MarketData], opts: &AggregateOptions) -> SessionAgg {
+    // Work in time order so open/close come from the earliest/latest bar even when the
+    // input isn't sorted. The sort is stable, so equal timestamps keep input order.
+    let mut bars = bars.to_vec();
+    bars.sort_by_cached_key(|r| parse_ts_to_naive(&r.timestamp));
+
+    let first = bars[0];
+    let last = bars[bars.len() - 1];
+
+    // Open/close become the median of the first/last `boundary_smoothing` bars. The
+    // open is settled first so `open_crosses` counts against the exported open.
+    let smoothing = opts.boundary_smoothing.min(bars.len());
+    let (open, smoothed_close) = if opts.boundary_smoothing > 1 {
+        let mut first_opens: Vec<f64> = bars[..smoothing].iter().map(|r| r.open).collect();
+        let mut last_closes: Vec<f64> = bars[bars.len() - smoothing..].iter().map(|r| r.close).collect();
+        (median(&mut first_opens), Some(median(&mut last_closes)))
+    } else {
+        (first.open, None)
+    };
+
+    let mut v = SessionAgg {
+        date,
+        session,
+        open,
+        high: first.high,
+        low: first.low,
+        close: first.close,
+        volume: first.volume,
+        high_ts: first.timestamp.clone(),
+        low_ts: first.timestamp.clone(),
+        open_ts: first.timestamp.clone(),
+        close_ts: last.timestamp.clone(),
+        pattern: String::new(),
+        open_crosses: 0,
+        body_ratio: 0.0,
+        upper_wick_ratio: 0.0,
+        lower_wick_ratio: 0.0,
+        mfe: 0.0,
+        vwap: 0.0,
+    };
+    let mut price_volume = typical_price(first.high, first.low, first.close) * first.volume;
+
+    for r in &bars[1..] {
+        if r.high > v.high {
+            v.high = r.high;
+            v.high_ts = r.timestamp.clone();
+        }
+        if r.low < v.low {
+            v.low = r.low;
+            v.low_ts = r.timestamp.clone();
+        }
+        if r.low < v.open && r.high > v.open {
+            v.open_crosses += 1;
         }
-    });
+        v.close = r.close;
+        v.volume += r.volume;
+        price_volume += typical_price(r.high, r.low, r.close) * r.volume;
+    }
+    if v.volume != 0.0 {
+        v.vwap = price_volume / v.volume;
+    }
 
-    out_aggs
+    if let Some(close) = smoothed_close {
+        v.close = close;
+    }
+
+    if opts.compute_patterns {
+        v.pattern = pattern_from_ohlc_cfg(v.open, v.high, v.low, v.close, &opts.candle_config);
+    }
+    if let Some(m) = candle_metrics(v.open, v.high, v.low, v.close, opts.candle_config.eps) {
+        v.body_ratio = m.body_ratio;
+        v.upper_wick_ratio = m.upper_wick_ratio;
+        v.lower_wick_ratio = m.lower_wick_ratio;
+    }
+    let direction = (v.close - v.open).signum();
+    if direction != 0.0 {
+        v.mfe = bars.iter()
+            .map(|r| if direction > 0.0 { r.high - v.open } else { v.open - r.low })
+            .fold(0.0, f64::max);
+    }
+    v
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// Write the raw per-session rows produced by `aggregate_sessions`.
+pub fn write_sessions_csv(sessions: &[SessionAgg], file_path: &str) -> Result<(), Box<dyn Error>> {
+    write_csv(sessions, file_path)
+}
+
+/// Index session aggregates by `(date, session)` for O(1) lookups.
+/// If a key appears more than once, the last occurrence wins.
+pub fn by_date_session(aggs: &[SessionAgg]) -> HashMap<(String, Session), &SessionAgg> {
+    aggs.iter().map(|agg| ((agg.date.clone(), agg.session), agg)).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SessionLevels {
+    pub q25: f64,
+    pub mid: f64,
+    pub q75: f64,
+}
+
+/// Quartile levels of the session range: `low + frac * (high - low)`.
+pub fn session_levels(agg: &SessionAgg) -> SessionLevels {
+    let range = agg.high - agg.low;
+    SessionLevels {
+        q25: agg.low + 0.25 * range,
+        mid: agg.low + 0.5 * range,
+        q75: agg.low + 0.75 * range,
+    }
+}
+
+pub fn all_session_levels(aggs: &[SessionAgg]) -> Vec<SessionLevels> {
+    aggs.iter().map(session_levels).collect()
+}
+
+/// Session carrying the most volume among `day_sessions`, with that volume.
+/// On a tie the earlier session wins.
+pub fn max_volume_session(day_sessions: &[SessionAgg]) -> Option<(Session, f64)> {
+    max_volume_session_of(day_sessions.iter())
+}
+
+pub(crate) fn max_volume_session_of<'a>(sessions: impl Iterator<Item = &'a SessionAgg>) -> Option<(Session, f64)> {
+    sessions.fold(None, |best: Option<(Session, f64)>, s| match best {
+        Some((b_session, b_volume)) if b_volume > s.volume
+            || (b_volume == s.volume && b_session < s.session) => best,
+        _ => Some((s.session, s.volume)),
+    })
+}
+
+/// Overlap of two sessions' `[low, high]` ranges as intersection over union:
+/// 1.0 for identical ranges, 0.0 for ranges that don't touch.
+pub fn session_range_overlap(a: &SessionAgg, b: &SessionAgg) -> f64 {
+    let intersection = (a.high.min(b.high) - a.low.max(b.low)).max(0.0);
+    let union = a.high.max(b.high) - a.low.min(b.low);
+    if union <= 0.0 {
+        // Both ranges are the same single price.
+        return if a.low == b.low { 1.0 } else { 0.0 };
+    }
+    intersection / union
+}
+
+/// Pairwise `session_range_overlap` for one day's sessions; `matrix[i][j]` compares
+/// `day_sessions[i]` with `day_sessions[j]`.
+pub fn session_overlap_matrix(day_sessions: &[SessionAgg]) -> Vec<Vec<f64>> {
+    day_sessions.iter()
+        .map(|a| day_sessions.iter().map(|b| session_range_overlap(a, b)).collect())
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -95,7 +267,7 @@ pub fn find_ny_high_low(sessions: &[SessionAgg]) -> HashMap<String, NyCombinedDa
         match s_agg.session {
             Session::NYAM | Session::NYL | Session::NYPM => {
                 ny_map.entry(s_agg.date.clone())
-                      .or_insert_with(Vec::new)
+                      .or_default()
                       .push(s_agg);
             },
             _ => {},
@@ -135,15 +307,136 @@ pub fn find_ny_high_low(sessions: &[SessionAgg]) -> HashMap<String, NyCombinedDa
 
 impl CsvRecord for SessionAgg {
     fn headers() -> &'static [&'static str] {
-        &["date", "session", "open", "high", "low", "close", "volume", "pattern"]
+        &["date", "session", "open", "high", "low", "close", "volume", "pattern", "open_crosses",
+          "body_ratio", "upper_wick_ratio", "lower_wick_ratio", "mfe", "vwap"]
     }
 
     fn record(&self) -> Vec<String> {
+        self.record_fmt(FloatFormat::default())
+    }
+
+    fn record_fmt(&self, fmt: FloatFormat) -> Vec<String> {
         vec![
             self.date.clone(), self.session.as_str().to_string(),
-            format!("{:.6}", self.open), format!("{:.6}", self.high),
-            format!("{:.6}", self.low), format!("{:.6}", self.close),
-            format!("{:.6}", self.volume), self.pattern.clone(),
+            fmt.format(self.open), fmt.format(self.high),
+            fmt.format(self.low), fmt.format(self.close),
+            fmt.format(self.volume), self.pattern.clone(),
+            self.open_crosses.to_string(),
+            fmt.format(self.body_ratio), fmt.format(self.upper_wick_ratio),
+            fmt.format(self.lower_wick_ratio), fmt.format(self.mfe),
+            fmt.format(self.vwap),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(ts: &str, open: f64, high: f64, low: f64, close: f64) -> MarketData {
+        MarketData { timestamp: ts.to_string(), open, high, low, close, volume: 100.0, source: None }
+    }
+
+    fn ln_bars() -> Vec<MarketData> {
+        vec![
+            bar("2024-01-02T08:00:00", 130.0, 131.0, 99.0, 100.0), // spiky open
+            bar("2024-01-02T09:00:00", 100.0, 103.0, 99.5, 102.0),
+            bar("2024-01-02T10:00:00", 102.0, 104.0, 101.0, 103.0),
+            bar("2024-01-02T11:00:00", 103.0, 106.0, 102.5, 105.0),
+            bar("2024-01-02T12:00:00", 105.0, 107.0, 104.0, 106.0),
+            bar("2024-01-02T13:00:00", 106.0, 108.0, 90.0, 91.0), // spiky close
         ]
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn boundary_smoothing_of_one_matches_default_aggregation() {
+        let data = ln_bars();
+        let opts = AggregateOptions { boundary_smoothing: 1, ..AggregateOptions::default() };
+
+        let smoothed = aggregate_sessions_with(&data, &opts);
+        assert_eq!(smoothed, aggregate_sessions(&data));
+        assert_eq!(smoothed[0].open, 130.0);
+        assert_eq!(smoothed[0].close, 91.0);
+    }
+
+    #[test]
+    fn boundary_smoothing_uses_median_of_boundary_bars() {
+        let data = ln_bars();
+        let opts = AggregateOptions { boundary_smoothing: 3, ..AggregateOptions::default() };
+
+        let aggs = aggregate_sessions_with(&data, &opts);
+        assert_eq!(aggs.len(), 1);
+        assert_eq!(aggs[0].open, 102.0);
+        assert_eq!(aggs[0].close, 105.0);
+        assert_eq!(aggs[0].high, 131.0);
+        assert_eq!(aggs[0].low, 90.0);
+        // Crossings are counted against the smoothed open: the 09:00, 10:00 and 13:00
+        // bars straddle 102, none straddle the raw 130.
+        assert_eq!(aggs[0].open_crosses, 3);
+        assert_eq!(aggregate_sessions(&data)[0].open_crosses, 0);
+    }
+
+    #[test]
+    fn open_and_close_follow_timestamps_not_input_order() {
+        let mut data = ln_bars();
+        data.reverse();
+
+        let aggs = aggregate_sessions(&data);
+        assert_eq!(aggs[0].open, 130.0);
+        assert_eq!(aggs[0].close, 91.0);
+        assert_eq!(aggs[0].open_ts, "2024-01-02T08:00:00");
+        assert_eq!(aggs[0].close_ts, "2024-01-02T13:00:00");
+        assert_eq!(aggs, aggregate_sessions(&ln_bars()));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_aggregation_matches_serial() {
+        let mut data = ln_bars();
+        data.extend(ln_bars().into_iter().map(|mut r| {
+            r.timestamp = r.timestamp.replace("2024-01-02", "2024-01-03");
+            r
+        }));
+        data.push(bar("2024-01-02T13:30:00", 91.0, 131.0, 90.0, 95.0)); // ties the high
+
+        assert_eq!(aggregate_sessions_parallel(&data), aggregate_sessions(&data));
+    }
+
+    #[test]
+    fn vwap_weights_typical_price_by_volume() {
+        let mut data = ln_bars();
+        let sessions = aggregate_sessions(&data);
+        let expected = data.iter().map(|r| typical_price(r.high, r.low, r.close)).sum::<f64>() / data.len() as f64;
+        assert!((sessions[0].vwap - expected).abs() < 1e-9);
+
+        for r in &mut data {
+            r.volume = 0.0;
+        }
+        assert_eq!(aggregate_sessions(&data)[0].vwap, 0.0);
+    }
+
+    #[test]
+    fn midnight_bar_groups_the_same_whether_stamped_00_or_24() {
+        let stamped = |midnight: &str| vec![
+            bar("2024.01.01T22:00:00", 100.0, 102.0, 99.0, 101.0),
+            bar("2024.01.01T23:00:00", 101.0, 103.0, 100.0, 102.0),
+            bar(midnight, 102.0, 104.0, 101.0, 103.0),
+            bar("2024.01.02T01:00:00", 103.0, 105.0, 102.0, 104.0),
+        ];
+        let (as_24, as_00) = (stamped("2024.01.01T24:00:00"), stamped("2024.01.02T00:00:00"));
+
+        for midnight_session in [Session::NYPM, Session::AS, Session::Unknown] {
+            let mut opts = AggregateOptions::default();
+            opts.session_config.midnight_session = midnight_session;
+            let keys = |data: &[MarketData]| -> Vec<(String, Session, f64)> {
+                aggregate_sessions_with(data, &opts).into_iter().map(|s| (s.date, s.session, s.close)).collect()
+            };
+            assert_eq!(keys(&as_24), keys(&as_00), "{:?}", midnight_session);
+        }
+
+        let mut opts = AggregateOptions::default();
+        opts.session_config.midnight_session = Session::NYPM;
+        let keys: Vec<_> = aggregate_sessions_with(&as_00, &opts).into_iter().map(|s| (s.date, s.session)).collect();
+        assert_eq!(keys, vec![("2024.01.01".to_string(), Session::NYPM), ("2024.01.02".to_string(), Session::AS)]);
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use serde::Serialize;
+
+use crate::data_engine::{iso_week_key, parse_ts_to_naive};
+use crate::session_data_agg::SessionAgg;
+use crate::session_type::Session;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WeeklyExcursion {
+    pub week: String,
+    pub start_date: String,
+    pub max_drawdown: f64,
+    pub max_run_up: f64,
+}
+
+// Session high and low in the order they printed, judged by `high_ts`/`low_ts`.
+fn extremes_in_order(s_agg: &SessionAgg) -> [f64; 2] {
+    let high_first = match (parse_ts_to_naive(&s_agg.high_ts), parse_ts_to_naive(&s_agg.low_ts)) {
+        (Some(h), Some(l)) => h <= l,
+        _ => s_agg.high_ts <= s_agg.low_ts,
+    };
+    if high_first { [s_agg.high, s_agg.low] } else { [s_agg.low, s_agg.high] }
+}
+
+/// Largest peak-to-trough decline and trough-to-peak rise within each ISO week,
+/// walking the week's session extremes in chronological order from the week open.
+pub fn weekly_excursions(sessions: &[SessionAgg]) -> Vec<WeeklyExcursion> {
+    let mut weekly_map: HashMap<String, Vec<&SessionAgg>> = HashMap::new();
+
+    for s_agg in sessions {
+        let ndt = match parse_ts_to_naive(&s_agg.date) {
+            Some(dt) => dt,
+            None => continue,
+        };
+        let week_key = iso_week_key(&ndt);
+        weekly_map.entry(week_key).or_default().push(s_agg);
+    }
+
+    let mut result: Vec<WeeklyExcursion> = weekly_map.into_iter().map(|(week, mut week_sessions)| {
+        week_sessions.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.session.cmp(&b.session)));
+
+        let week_open = week_sessions[0].open;
+        let mut peak = week_open;
+        let mut trough = week_open;
+        let mut max_drawdown: f64 = 0.0;
+        let mut max_run_up: f64 = 0.0;
+
+        for price in week_sessions.iter().flat_map(|s| extremes_in_order(s)) {
+            peak = peak.max(price);
+            trough = trough.min(price);
+            max_drawdown = max_drawdown.max(peak - price);
+            max_run_up = max_run_up.max(price - trough);
+        }
+
+        WeeklyExcursion {
+            week,
+            start_date: week_sessions[0].date.clone(),
+            max_drawdown,
+            max_run_up,
+        }
+    }).collect();
+
+    result.sort_by(|a, b| a.week.cmp(&b.week));
+    result
+}
+
+/// How much of the day's largest move away from the open was given back afterwards.
+///
+/// The day's path is its open followed by each session's high/low in the order they
+/// printed and finally the close. The excursion is the point farthest from the open;
+/// the fraction is the deepest later pullback from that point divided by the excursion
+/// (above 1.0 when price went back through the open). Days with no movement give 0.0.
+pub fn intraday_retracement(sessions: &[SessionAgg]) -> Vec<(String, f64)> {
+    let mut daily_map: HashMap<&str, Vec<&SessionAgg>> = HashMap::new();
+    for s_agg in sessions {
+        daily_map.entry(s_agg.date.as_str()).or_default().push(s_agg);
+    }
+
+    let mut result: Vec<(String, f64)> = daily_map.into_iter().map(|(date, mut day_sessions)| {
+        day_sessions.sort_by_key(|s| s.session);
+
+        let open = day_sessions[0].open;
+        let close = day_sessions[day_sessions.len() - 1].close;
+        let path: Vec<f64> = std::iter::once(open)
+            .chain(day_sessions.iter().flat_map(|s| extremes_in_order(s)))
+            .chain(std::iter::once(close))
+            .collect();
+
+        let (peak_idx, _) = path.iter().enumerate()
+            .fold((0, 0.0), |best, (i, p)| {
+                let dist = (p - open).abs();
+                if dist > best.1 { (i, dist) } else { best }
+            });
+        let peak = path[peak_idx];
+        let excursion = (peak - open).abs();
+        let direction = (peak - open).signum();
+
+        let retraced = path[peak_idx..].iter()
+            .map(|p| (peak - p) * direction)
+            .fold(0.0, f64::max);
+
+        let fraction = if excursion > 0.0 { retraced / excursion } else { 0.0 };
+        (date.to_string(), fraction)
+    }).collect();
+
+    result.sort_by(|a, b| a.0.cmp(&b.0));
+    result
+}
+
+/// Days where some session takes out an earlier session's high (or low) and a
+/// session after the sweep closes beyond the swept session's open in the other
+/// direction: below it after a high sweep, above it after a low sweep.
+pub fn sweep_reversal_days(sessions: &[SessionAgg]) -> Vec<String> {
+    let mut daily_map: HashMap<&str, Vec<&SessionAgg>> = HashMap::new();
+    for s_agg in sessions {
+        daily_map.entry(s_agg.date.as_str()).or_default().push(s_agg);
+    }
+
+    let mut result: Vec<String> = daily_map.into_iter().filter_map(|(date, mut day_sessions)| {
+        day_sessions.sort_by_key(|s| s.session);
+        let n = day_sessions.len();
+        let found = (0..n).any(|i| {
+            let swept = day_sessions[i];
+            (i + 1..n).any(|j| {
+                let sweeper = day_sessions[j];
+                let took_high = sweeper.high > swept.high;
+                let took_low = sweeper.low < swept.low;
+                day_sessions[j + 1..].iter().any(|later| {
+                    (took_high && later.close < swept.open) || (took_low && later.close > swept.open)
+                })
+            })
+        });
+        found.then(|| date.to_string())
+    }).collect();
+
+    result.sort();
+    result
+}
+
+/// A day's return split at its first session open. Each is a change divided by the
+/// absolute price it starts from; for positive prices
+/// `(1 + overnight) * (1 + intraday) = 1 + total`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReturnSplit {
+    pub date: String,
+    /// Prior day's last session close to this day's first session open.
+    pub overnight: f64,
+    /// First session open to last session close.
+    pub intraday: f64,
+    pub total: f64,
+}
+
+/// `ReturnSplit` for every day that has a prior day in `sessions`, in date order.
+pub fn return_decomposition(sessions: &[SessionAgg]) -> Vec<ReturnSplit> {
+    let mut daily_map: HashMap<&str, Vec<&SessionAgg>> = HashMap::new();
+    for s_agg in sessions {
+        daily_map.entry(s_agg.date.as_str()).or_default().push(s_agg);
+    }
+
+    // (date, first open, last close) per day, in date order.
+    let mut days: Vec<(&str, f64, f64)> = daily_map.into_iter().map(|(date, mut day_sessions)| {
+        day_sessions.sort_by_key(|s| s.session);
+        (date, day_sessions[0].open, day_sessions[day_sessions.len() - 1].close)
+    }).collect();
+    days.sort_by_key(|(date, _, _)| parse_ts_to_naive(date));
+
+    days.windows(2).filter_map(|pair| {
+        let (_, _, prev_close) = pair[0];
+        let (date, open, close) = pair[1];
+        if prev_close == 0.0 || open == 0.0 {
+            return None;
+        }
+        Some(ReturnSplit {
+            date: date.to_string(),
+            overnight: (open - prev_close) / prev_close.abs(),
+            intraday: (close - open) / open.abs(),
+            total: (close - prev_close) / prev_close.abs(),
+        })
+    }).collect()
+}
+
+/// Running count of bullish minus bearish sessions through one day, after each
+/// session in chronological order. Flat sessions leave the count unchanged.
+pub fn session_cumulative_delta(day_sessions: &[SessionAgg]) -> Vec<(Session, i32)> {
+    let mut ordered: Vec<&SessionAgg> = day_sessions.iter().collect();
+    ordered.sort_by_key(|s| s.session);
+
+    let mut delta = 0;
+    ordered.into_iter().map(|s| {
+        if s.close > s.open {
+            delta += 1;
+        } else if s.close < s.open {
+            delta -= 1;
+        }
+        (s.session, delta)
+    }).collect()
+}
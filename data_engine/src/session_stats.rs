@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, HashMap};
+use chrono::{Datelike, NaiveDate, Timelike, Weekday};
+use serde::Serialize;
+
+use crate::data_engine::{parse_ts_to_naive, MarketData};
+use crate::session_data_agg::SessionAgg;
+use crate::session_type::{Session, SessionConfig};
+use crate::week_day_data::PeriodAgg;
+
+/// Average `high - low` of each session, split by the weekday of its date.
+pub fn session_range_by_weekday(sessions: &[SessionAgg]) -> HashMap<(Session, Weekday), f64> {
+    let mut sums: HashMap<(Session, Weekday), (f64, usize)> = HashMap::new();
+
+    for s_agg in sessions {
+        let ndt = match parse_ts_to_naive(&s_agg.date) {
+            Some(dt) => dt,
+            None => continue,
+        };
+        let entry = sums.entry((s_agg.session, ndt.weekday())).or_insert((0.0, 0));
+        entry.0 += s_agg.high - s_agg.low;
+        entry.1 += 1;
+    }
+
+    sums.into_iter()
+        .map(|(key, (total, count))| (key, total / count as f64))
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HandoffStats {
+    pub count: usize,
+    pub continuations: usize,
+    pub frequency: f64,
+}
+
+/// For every ordered pair of sessions within a day (earlier, later), how often the later
+/// session opens beyond the earlier session's close in the direction the earlier session
+/// moved. Pairs where the earlier session closed flat are left out.
+pub fn session_handoff_stats(sessions: &[SessionAgg]) -> HashMap<(Session, Session), HandoffStats> {
+    let mut daily_map: HashMap<&str, Vec<&SessionAgg>> = HashMap::new();
+    for s_agg in sessions {
+        daily_map.entry(s_agg.date.as_str()).or_default().push(s_agg);
+    }
+
+    let mut stats: HashMap<(Session, Session), HandoffStats> = HashMap::new();
+
+    for mut day_sessions in daily_map.into_values() {
+        day_sessions.sort_by_key(|s| s.session);
+
+        for (i, earlier) in day_sessions.iter().enumerate() {
+            let direction = earlier.close - earlier.open;
+            if direction == 0.0 { continue; }
+
+            for later in &day_sessions[i + 1..] {
+                let gap = later.open - earlier.close;
+                let entry = stats.entry((earlier.session, later.session)).or_default();
+                entry.count += 1;
+                if gap * direction > 0.0 {
+                    entry.continuations += 1;
+                }
+            }
+        }
+    }
+
+    for entry in stats.values_mut() {
+        entry.frequency = entry.continuations as f64 / entry.count as f64;
+    }
+    stats
+}
+
+/// For each pattern of `session`, how many times it occurred and the fraction of those
+/// days whose next trading day (the next row of `daily`) closed above its open.
+/// Days without a following daily row are not counted.
+pub fn predictive_power(sessions: &[SessionAgg], daily: &[PeriodAgg], session: Session) -> HashMap<String, (usize, f64)> {
+    let day_key = |date: &str| parse_ts_to_naive(date).map(|dt| dt.date());
+
+    let mut days: Vec<&PeriodAgg> = daily.iter().collect();
+    days.sort_by_key(|d| day_key(&d.date));
+    let next_bullish: HashMap<_, bool> = days.windows(2)
+        .filter_map(|pair| day_key(&pair[0].date).map(|d| (d, pair[1].close > pair[1].open)))
+        .collect();
+
+    let mut tallies: HashMap<String, (usize, usize)> = HashMap::new();
+    for s_agg in sessions.iter().filter(|s| s.session == session) {
+        let bullish = match day_key(&s_agg.date).and_then(|d| next_bullish.get(&d)) {
+            Some(b) => *b,
+            None => continue,
+        };
+        let entry = tallies.entry(s_agg.pattern.clone()).or_insert((0, 0));
+        entry.0 += 1;
+        if bullish {
+            entry.1 += 1;
+        }
+    }
+
+    tallies.into_iter()
+        .map(|(pattern, (count, bullish))| (pattern, (count, bullish as f64 / count as f64)))
+        .collect()
+}
+
+/// Dates (`YYYY-MM-DD`) where the first bar of the day moves by exactly one hour
+/// against the previous day and the next day keeps the new time, i.e. likely
+/// daylight-saving switches in the feed's clock. One-off late starts are ignored,
+/// including on the last day, which has no next day to confirm it.
+pub fn detect_dst_candidates(data: &[MarketData]) -> Vec<String> {
+    let mut first_bar: BTreeMap<NaiveDate, u32> = BTreeMap::new();
+    for r in data {
+        if let Some(dt) = parse_ts_to_naive(&r.timestamp) {
+            let minute = dt.hour() * 60 + dt.minute();
+            first_bar.entry(dt.date())
+                .and_modify(|m| *m = (*m).min(minute))
+                .or_insert(minute);
+        }
+    }
+
+    let days: Vec<(NaiveDate, u32)> = first_bar.into_iter().collect();
+    let mut out = Vec::new();
+    for i in 1..days.len() {
+        let (date, start) = days[i];
+        let shift = start.abs_diff(days[i - 1].1);
+        let persists = days.get(i + 1).is_some_and(|next| next.1 == start);
+        if shift == 60 && persists {
+            out.push(date.format("%Y-%m-%d").to_string());
+        }
+    }
+    out
+}
+
+/// Average shape of a session within its day. Prices are positions in the day's
+/// range: 0.0 is the daily low, 1.0 the daily high.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTemplate {
+    pub session: Session,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub days: usize,
+}
+
+/// One `SessionTemplate` per session, averaged over every day that has both a daily
+/// row and that session. Flat days are skipped. Sorted by session.
+pub fn typical_day_template(daily: &[PeriodAgg], sessions: &[SessionAgg]) -> Vec<SessionTemplate> {
+    let day_key = |date: &str| parse_ts_to_naive(date).map(|dt| dt.date());
+    let days: HashMap<NaiveDate, &PeriodAgg> = daily.iter()
+        .filter_map(|d| day_key(&d.date).map(|k| (k, d)))
+        .collect();
+
+    let mut sums: HashMap<Session, SessionTemplate> = HashMap::new();
+    for s_agg in sessions {
+        let day = match day_key(&s_agg.date).and_then(|d| days.get(&d)) {
+            Some(day) => day,
+            None => continue,
+        };
+        let range = day.high - day.low;
+        if range <= 0.0 { continue; }
+        let pos = |price: f64| (price - day.low) / range;
+
+        let entry = sums.entry(s_agg.session).or_insert_with(|| SessionTemplate {
+            session: s_agg.session, open: 0.0, high: 0.0, low: 0.0, close: 0.0, days: 0,
+        });
+        entry.open += pos(s_agg.open);
+        entry.high += pos(s_agg.high);
+        entry.low += pos(s_agg.low);
+        entry.close += pos(s_agg.close);
+        entry.days += 1;
+    }
+
+    let mut result: Vec<SessionTemplate> = sums.into_values().map(|mut t| {
+        let n = t.days as f64;
+        t.open /= n;
+        t.high /= n;
+        t.low /= n;
+        t.close /= n;
+        t
+    }).collect();
+    result.sort_by_key(|t| t.session);
+    result
+}
+
+/// Average `mfe` of each session over all days.
+pub fn average_mfe_by_session(sessions: &[SessionAgg]) -> HashMap<Session, f64> {
+    let mut sums: HashMap<Session, (f64, usize)> = HashMap::new();
+    for s_agg in sessions {
+        let entry = sums.entry(s_agg.session).or_insert((0.0, 0));
+        entry.0 += s_agg.mfe;
+        entry.1 += 1;
+    }
+    sums.into_iter()
+        .map(|(session, (total, count))| (session, total / count as f64))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconcileReport {
+    pub raw_total: f64,
+    pub session_total: f64,
+    pub daily_total: f64,
+    /// `raw_total - session_total`; volume of bars outside every session.
+    pub session_shortfall: f64,
+    /// `raw_total - daily_total`.
+    pub daily_shortfall: f64,
+    /// All three totals agree to within 1e-9 of the raw total.
+    pub balanced: bool,
+}
+
+/// Compare total volume across the raw bars and both aggregate layers.
+pub fn reconcile_volume(raw: &[MarketData], sessions: &[SessionAgg], daily: &[PeriodAgg]) -> ReconcileReport {
+    let raw_total: f64 = raw.iter().map(|r| r.volume).sum();
+    let session_total: f64 = sessions.iter().map(|s| s.volume).sum();
+    let daily_total: f64 = daily.iter().map(|d| d.volume).sum();
+    let session_shortfall = raw_total - session_total;
+    let daily_shortfall = raw_total - daily_total;
+    let tolerance = 1e-9 * raw_total.abs().max(1.0);
+
+    ReconcileReport {
+        raw_total,
+        session_total,
+        daily_total,
+        session_shortfall,
+        daily_shortfall,
+        balanced: session_shortfall.abs() <= tolerance && daily_shortfall.abs() <= tolerance,
+    }
+}
+
+/// Minutes from each session's start hour to its `high_ts` and `low_ts`, per session,
+/// collected across all days for histogramming. Offsets wrap past midnight, so a
+/// 22..=3 window puts a 01:00 high at 180. Timestamps that don't parse are left out.
+pub fn session_extreme_time_distribution(sessions: &[SessionAgg]) -> HashMap<Session, (Vec<u32>, Vec<u32>)> {
+    session_extreme_time_distribution_with(sessions, &SessionConfig::default())
+}
+
+/// `session_extreme_time_distribution` for sessions built with `cfg`.
+pub fn session_extreme_time_distribution_with(
+    sessions: &[SessionAgg],
+    cfg: &SessionConfig,
+) -> HashMap<Session, (Vec<u32>, Vec<u32>)> {
+    let mut result: HashMap<Session, (Vec<u32>, Vec<u32>)> = HashMap::new();
+    for s_agg in sessions {
+        let start = match cfg.start_hour(s_agg.session) {
+            Some(hour) => hour * 60,
+            None => continue,
+        };
+        let offset = |ts: &str| {
+            parse_ts_to_naive(ts).map(|dt| (dt.hour() * 60 + dt.minute() + 1440 - start) % 1440)
+        };
+        let (highs, lows) = result.entry(s_agg.session).or_default();
+        highs.extend(offset(&s_agg.high_ts));
+        lows.extend(offset(&s_agg.low_ts));
+    }
+    result
+}
+
+/// `|close - open|` of each day over the summed `high - low` of its sessions: near 1.0
+/// on clean trend days, near 0.0 on choppy ones. Days with no sessions are skipped;
+/// a zero path gives 0.0. In `daily` order.
+pub fn day_efficiency(daily: &[PeriodAgg], sessions: &[SessionAgg]) -> Vec<(String, f64)> {
+    let day_key = |date: &str| parse_ts_to_naive(date).map(|dt| dt.date());
+    let mut paths: HashMap<NaiveDate, f64> = HashMap::new();
+    for s_agg in sessions {
+        if let Some(date) = day_key(&s_agg.date) {
+            *paths.entry(date).or_insert(0.0) += s_agg.high - s_agg.low;
+        }
+    }
+
+    daily.iter().filter_map(|day| {
+        let path = *day_key(&day.date).and_then(|d| paths.get(&d))?;
+        let efficiency = if path > 0.0 { (day.close - day.open).abs() / path } else { 0.0 };
+        Some((day.date.clone(), efficiency))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_type::SessionWindow;
+
+    fn bar(ts: &str) -> MarketData {
+        MarketData { timestamp: ts.to_string(), open: 1.0, high: 1.0, low: 1.0, close: 1.0, volume: 1.0, source: None }
+    }
+
+    #[test]
+    fn dst_candidates_need_the_next_day_to_confirm_the_shift() {
+        let data: Vec<MarketData> = ["2024-03-04T01:00:00", "2024-03-05T02:00:00", "2024-03-06T02:00:00", "2024-03-07T03:00:00"]
+            .into_iter().map(bar).collect();
+        assert_eq!(detect_dst_candidates(&data), vec!["2024-03-05".to_string()]);
+    }
+
+    #[test]
+    fn extreme_offsets_wrap_past_midnight() {
+        let mut cfg = SessionConfig::default();
+        cfg.windows[0] = SessionWindow { session: Session::AS, start_hour: 22, end_hour: 3 };
+        let session = SessionAgg {
+            date: "2024-03-04".to_string(),
+            session: Session::AS,
+            open: 1.0, high: 2.0, low: 0.5, close: 1.5, volume: 0.0,
+            high_ts: "2024-03-05T01:00:00".to_string(),
+            low_ts: "2024-03-04T22:30:00".to_string(),
+            open_ts: "2024-03-04T22:00:00".to_string(),
+            close_ts: "2024-03-05T03:00:00".to_string(),
+            pattern: String::new(),
+            open_crosses: 0,
+            body_ratio: 0.0, upper_wick_ratio: 0.0, lower_wick_ratio: 0.0, mfe: 0.0, vwap: 0.0,
+        };
+        let dist = session_extreme_time_distribution_with(&[session], &cfg);
+        assert_eq!(dist[&Session::AS], (vec![180], vec![30]));
+    }
+}
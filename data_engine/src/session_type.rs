@@ -2,7 +2,8 @@ use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,Serialize, Deserialize)]
+// Variants are declared in chronological order, so `Ord` sorts sessions through the day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Session {
     AS,
     LN,
@@ -12,6 +13,59 @@ pub enum Session {
     Unknown,
 }
 
+/// Inclusive hour range of one session. A window with `start_hour > end_hour`
+/// wraps past midnight (e.g. 22..=3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionWindow {
+    pub session: Session,
+    pub start_hour: u32,
+    pub end_hour: u32,
+}
+
+impl SessionWindow {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour..=self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour <= self.end_hour
+        }
+    }
+}
+
+/// Session bucketing options. `Default` matches `Session::from_hour`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionConfig {
+    /// Session for the midnight bar, whether stamped `00:00` or `24:00`.
+    /// `Session::Unknown` (the default) leaves it out of session aggregates
+    /// unless one of the `windows` covers hour 0.
+    pub midnight_session: Session,
+    /// Hour windows, checked in order; hours outside every window are `Unknown`.
+    pub windows: [SessionWindow; 5],
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        let window = |session, start_hour, end_hour| SessionWindow { session, start_hour, end_hour };
+        SessionConfig {
+            midnight_session: Session::Unknown,
+            windows: [
+                window(Session::AS, 1, 7),
+                window(Session::LN, 8, 14),
+                window(Session::NYAM, 15, 18),
+                window(Session::NYL, 19, 20),
+                window(Session::NYPM, 21, 23),
+            ],
+        }
+    }
+}
+
+impl SessionConfig {
+    /// First hour of `session`'s window, if it has one.
+    pub fn start_hour(&self, session: Session) -> Option<u32> {
+        self.windows.iter().find(|w| w.session == session).map(|w| w.start_hour)
+    }
+}
+
 impl Session {
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -24,26 +78,55 @@ impl Session {
         }
     }
 
+    /// Inverse of `as_str`.
+    pub fn from_label(label: &str) -> Option<Self> {
+        Some(match label {
+            "AS" => Session::AS,
+            "LN" => Session::LN,
+            "NYAM" => Session::NYAM,
+            "NYL" => Session::NYL,
+            "NYPM" => Session::NYPM,
+            "Unknown" => Session::Unknown,
+            _ => return None,
+        })
+    }
+
+    /// First hour `from_hour` assigns to this session.
+    pub fn start_hour(&self) -> Option<u32> {
+        SessionConfig::default().start_hour(*self)
+    }
+
     pub fn from_hour(hour: u32) -> Self {
-        match hour {
-            1..=7 => Session::AS,
-            8..=14 => Session::LN,
-            15..=18 => Session::NYAM,
-            19..=20 => Session::NYL,
-            21..=23 => Session::NYPM,
-            _ => Session::Unknown,
+        Session::from_hour_with_config(hour, &SessionConfig::default())
+    }
+
+    /// Session whose `cfg.windows` entry covers `hour`. Hour `0` and hour `24`
+    /// (brokers that stamp midnight as `24:00`) map to `cfg.midnight_session` when
+    /// it is set.
+    pub fn from_hour_with_config(hour: u32, cfg: &SessionConfig) -> Self {
+        let hour = match hour {
+            24 => 0,
+            25.. => return Session::Unknown,
+            h => h,
+        };
+        if hour == 0 && cfg.midnight_session != Session::Unknown {
+            return cfg.midnight_session;
         }
+        cfg.windows.iter()
+            .find(|w| w.contains(hour))
+            .map(|w| w.session)
+            .unwrap_or(Session::Unknown)
     }
 
     pub fn from_timestamp(ts: &str) -> Self {
-        let time_part_opt = ts.split(['T', ' ']).nth(1);
-        if let Some(tp) = time_part_opt {
-            let hour_str = tp.split(':').next().unwrap_or("");
-            if let Ok(hour) = hour_str.parse::<u32>() {
-                return Session::from_hour(hour);
-            }
+        Session::from_timestamp_with_config(ts, &SessionConfig::default())
+    }
+
+    pub fn from_timestamp_with_config(ts: &str, cfg: &SessionConfig) -> Self {
+        match hour_from_timestamp(ts) {
+            Some(hour) => Session::from_hour_with_config(hour, cfg),
+            None => Session::Unknown,
         }
-        Session::Unknown
     }
 }
 
@@ -53,10 +136,41 @@ impl fmt::Display for Session {
     }
 }
 
+// Hour as written in the timestamp, so `24:00` is reported as 24 rather than rejected.
+fn hour_from_timestamp(ts: &str) -> Option<u32> {
+    let time_part = ts.split(['T', ' ']).nth(1)?;
+    time_part.split(':').next()?.parse::<u32>().ok()
+}
+
 pub fn session_from_timestamp(ts: &str) -> String {
     Session::from_timestamp(ts).as_str().to_string()
 }
 
 pub fn session_from_timestamp_enum(ts: &str) -> Session {
     Session::from_timestamp(ts)
-}
\ No newline at end of file
+}
+
+pub fn session_from_timestamp_enum_with_config(ts: &str, cfg: &SessionConfig) -> Session {
+    Session::from_timestamp_with_config(ts, cfg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midnight_bar_maps_to_configured_session_in_both_forms() {
+        let cfg = SessionConfig { midnight_session: Session::NYPM, ..SessionConfig::default() };
+
+        assert_eq!(Session::from_timestamp_with_config("2024-01-02T00:00:00", &cfg), Session::NYPM);
+        assert_eq!(Session::from_timestamp_with_config("2024-01-01T24:00:00", &cfg), Session::NYPM);
+        assert_eq!(Session::from_timestamp_with_config("2024.01.01 24:00", &cfg), Session::NYPM);
+    }
+
+    #[test]
+    fn midnight_bar_is_unknown_by_default() {
+        assert_eq!(Session::from_timestamp("2024-01-02T00:00:00"), Session::Unknown);
+        assert_eq!(Session::from_timestamp("2024-01-01T24:00:00"), Session::Unknown);
+        assert_eq!(Session::from_timestamp("2024-01-01T23:00:00"), Session::NYPM);
+    }
+}
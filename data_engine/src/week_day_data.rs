@@ -1,10 +1,8 @@
-use std::collections::HashMap;
-use crate::data_engine::{CsvRecord, MarketData, parse_ts_to_naive};
-use std::error::Error;
-use std::path::Path;
-use chrono::{Datelike, NaiveDate, NaiveDateTime, Weekday};
-use crate::candle_type::{pattern_from_ohlc, DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG, DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS};
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use chrono::{Datelike, NaiveDate, Weekday};
+use crate::data_engine::{AggregateOptions, CsvRecord, FloatFormat, MarketData, iso_week_key_date, parse_ts_to_naive};
+use crate::candle_type::{pattern_from_ohlc_cfg, pattern_from_ohlc_with, PatternParams};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)] 
@@ -25,24 +23,53 @@ impl CsvRecord for PeriodAgg {
     }
 
     fn record(&self) -> Vec<String> {
+        self.record_fmt(FloatFormat::default())
+    }
+
+    fn record_fmt(&self, fmt: FloatFormat) -> Vec<String> {
         vec![
             self.date.clone(),
-            format!("{:.6}", self.open),
-            format!("{:.6}", self.high),
-            format!("{:.6}", self.low),
-            format!("{:.6}", self.close),
-            format!("{:.6}", self.volume),
+            fmt.format(self.open),
+            fmt.format(self.high),
+            fmt.format(self.low),
+            fmt.format(self.close),
+            fmt.format(self.volume),
             self.members.clone(),
             self.pattern.clone(),
         ]
     }
 }
 
-pub fn aggregate_periods(data: &[MarketData]) -> (Vec<PeriodAgg>, Vec<PeriodAgg>, Vec<PeriodAgg>, Vec<PeriodAgg>, Vec<PeriodAgg>) {
+/// Daily, weekly, weekday, monthly and yearly rows, in that order.
+pub type PeriodTables = (Vec<PeriodAgg>, Vec<PeriodAgg>, Vec<PeriodAgg>, Vec<PeriodAgg>, Vec<PeriodAgg>);
+
+pub fn aggregate_periods(data: &[MarketData]) -> PeriodTables {
+    aggregate_periods_with(data, &AggregateOptions::default())
+}
+
+/// `aggregate_periods` over the bars whose timestamp parses, leaving out Saturday and
+/// Sunday bars when `skip_weekends`. Also returns how many bars were dropped for an
+/// unparseable timestamp.
+pub fn aggregate_periods_filtered(data: &[MarketData], skip_weekends: bool) -> (PeriodTables, usize) {
+    let mut unparseable = 0;
+    let kept: Vec<MarketData> = data.iter()
+        .filter(|r| match parse_ts_to_naive(&r.timestamp) {
+            Some(dt) => !(skip_weekends && matches!(dt.weekday(), Weekday::Sat | Weekday::Sun)),
+            None => {
+                unparseable += 1;
+                false
+            }
+        })
+        .cloned()
+        .collect();
+    (aggregate_periods(&kept), unparseable)
+}
+
+pub fn aggregate_periods_with(data: &[MarketData], opts: &AggregateOptions) -> PeriodTables {
     let mut aggs: HashMap<String, PeriodAgg> = HashMap::new();
 
     for r in data {
-        let date_part = r.timestamp.split(['T', ' ']).next().unwrap_or("").trim().replace('.', "-");
+        let date_part = opts.day_boundary.date_key(&r.timestamp).replace('.', "-");
 
         aggs.entry(date_part.clone())
             .and_modify(|agg| {
@@ -64,20 +91,222 @@ pub fn aggregate_periods(data: &[MarketData]) -> (Vec<PeriodAgg>, Vec<PeriodAgg>
     }
     
     let mut daily_aggs: Vec<PeriodAgg> = aggs.into_values().map(|mut agg| {
-        agg.pattern = pattern_from_ohlc(
-            agg.open, agg.high, agg.low, agg.close,
-            DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG,
-            DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS,
-        );
+        if opts.compute_patterns {
+            agg.pattern = pattern_from_ohlc_cfg(agg.open, agg.high, agg.low, agg.close, &opts.candle_config);
+        }
         agg
     }).collect();
+    // Roll-ups need the days in order so open/close come from the first/last day.
     daily_aggs.sort_by(|a, b| a.date.cmp(&b.date));
+    let mut weekly = rollup(&daily_aggs, opts, iso_week_key_date);
+    let mut weekday = rollup(&daily_aggs, opts, |d| d.weekday().to_string());
+    let mut monthly = rollup(&daily_aggs, opts, |d| d.format("%Y-%m").to_string());
+    let mut yearly = rollup(&daily_aggs, opts, |d| d.year().to_string());
+
+    opts.sort_order.apply(&mut daily_aggs, |a, b| a.date.cmp(&b.date));
+    for period in [&mut weekly, &mut monthly, &mut yearly] {
+        opts.sort_order.apply(period, |a, b| a.date.cmp(&b.date));
+    }
+    // Weekdays always run Monday to Sunday.
+    weekday.sort_by_key(|agg| agg.date.parse::<Weekday>().map(|w| w.num_days_from_monday()).unwrap_or(7));
+
+    (daily_aggs, weekly, weekday, monthly, yearly)
+}
+
+// Group chronological `daily` rows by `key_fn(date)`. The key becomes the row's `date`
+// and `members` lists the contributing days, comma-separated.
+fn rollup(daily: &[PeriodAgg], opts: &AggregateOptions, key_fn: impl Fn(NaiveDate) -> String) -> Vec<PeriodAgg> {
+    let mut order: Vec<String> = Vec::new();
+    let mut groups: HashMap<String, PeriodAgg> = HashMap::new();
 
-    (
-        daily_aggs,
-        Vec::new(), // weekly (placeholder)
-        Vec::new(), // weekday (placeholder)
-        Vec::new(), // monthly (placeholder)
-        Vec::new(), // yearly (placeholder)
-    )
-}
\ No newline at end of file
+    for day in daily {
+        let date = match parse_ts_to_naive(&day.date) {
+            Some(dt) => dt.date(),
+            None => continue,
+        };
+        let key = key_fn(date);
+        groups.entry(key.clone())
+            .and_modify(|agg| {
+                agg.high = agg.high.max(day.high);
+                agg.low = agg.low.min(day.low);
+                agg.close = day.close;
+                agg.volume += day.volume;
+                agg.members.push(',');
+                agg.members.push_str(&day.date);
+            })
+            .or_insert_with(|| {
+                order.push(key.clone());
+                PeriodAgg {
+                    date: key,
+                    open: day.open,
+                    high: day.high,
+                    low: day.low,
+                    close: day.close,
+                    volume: day.volume,
+                    members: day.date.clone(),
+                    pattern: String::new(),
+                }
+            });
+    }
+
+    order.into_iter().filter_map(|key| groups.remove(&key)).map(|mut agg| {
+        if opts.compute_patterns {
+            agg.pattern = pattern_from_ohlc_cfg(agg.open, agg.high, agg.low, agg.close, &opts.candle_config);
+        }
+        agg
+    }).collect()
+}
+
+/// How often each `pattern` occurs on each weekday. Rows whose date doesn't parse
+/// are skipped.
+pub fn pattern_frequency_by_weekday(aggs: &[PeriodAgg]) -> HashMap<Weekday, HashMap<String, usize>> {
+    let mut counts: HashMap<Weekday, HashMap<String, usize>> = HashMap::new();
+
+    for agg in aggs {
+        let ndt = match parse_ts_to_naive(&agg.date) {
+            Some(dt) => dt,
+            None => continue,
+        };
+        *counts.entry(ndt.weekday())
+            .or_default()
+            .entry(agg.pattern.clone())
+            .or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Most frequent daily `pattern` for each weekday across the dataset.
+/// Ties are broken alphabetically so the result is deterministic.
+pub fn modal_pattern_by_weekday(daily: &[PeriodAgg]) -> HashMap<Weekday, String> {
+    pattern_frequency_by_weekday(daily).into_iter()
+        .filter_map(|(weekday, patterns)| {
+            patterns.into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+                .map(|(pattern, _)| (weekday, pattern))
+        })
+        .collect()
+}
+
+/// Index daily aggregates by their `date` for O(1) lookups.
+/// If a date appears more than once, the last occurrence wins.
+pub fn by_date(aggs: &[PeriodAgg]) -> HashMap<String, &PeriodAgg> {
+    aggs.iter().map(|agg| (agg.date.clone(), agg)).collect()
+}
+
+/// Merge runs of consecutive same-direction days into single candles (open of the
+/// first day, close of the last, extreme high/low, summed volume). A new candle starts
+/// when direction flips; flat days (`close == open`) extend the current run.
+/// `members` lists the merged dates, comma-separated.
+pub fn merge_by_direction(daily: &[PeriodAgg]) -> Vec<PeriodAgg> {
+    merge_by_direction_with(daily, &PatternParams::default())
+}
+
+/// `merge_by_direction` with the merged candles classified by `params`.
+pub fn merge_by_direction_with(daily: &[PeriodAgg], params: &PatternParams) -> Vec<PeriodAgg> {
+    let mut merged: Vec<PeriodAgg> = Vec::new();
+    let mut run_bullish: Option<bool> = None;
+
+    for day in daily {
+        let day_bullish = if day.close > day.open {
+            Some(true)
+        } else if day.close < day.open {
+            Some(false)
+        } else {
+            None
+        };
+
+        let flips = matches!((run_bullish, day_bullish), (Some(run), Some(cur)) if run != cur);
+
+        match merged.last_mut() {
+            Some(agg) if !flips => {
+                agg.high = agg.high.max(day.high);
+                agg.low = agg.low.min(day.low);
+                agg.close = day.close;
+                agg.volume += day.volume;
+                agg.members.push(',');
+                agg.members.push_str(&day.date);
+            }
+            _ => {
+                merged.push(PeriodAgg {
+                    date: day.date.clone(),
+                    open: day.open,
+                    high: day.high,
+                    low: day.low,
+                    close: day.close,
+                    volume: day.volume,
+                    members: day.date.clone(),
+                    pattern: String::new(),
+                });
+                run_bullish = None;
+            }
+        }
+        if day_bullish.is_some() {
+            run_bullish = day_bullish;
+        }
+    }
+
+    for agg in &mut merged {
+        agg.pattern = pattern_from_ohlc_with(agg.open, agg.high, agg.low, agg.close, params);
+    }
+    merged
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Streak {
+    pub start_date: String,
+    pub end_date: String,
+    pub length: usize,
+}
+
+/// Longest run of consecutive bullish (`close > open`) days and the longest run of
+/// bearish days, as `(bullish, bearish)`. Flat days end both kinds of run; when there
+/// are no such days the streak has length 0. The first-found run wins ties.
+pub fn longest_streaks(daily: &[PeriodAgg]) -> (Streak, Streak) {
+    let mut best_bull = Streak::default();
+    let mut best_bear = Streak::default();
+    let directions: Vec<Option<Ordering>> = daily.iter().map(|d| d.close.partial_cmp(&d.open)).collect();
+    let mut run_start = 0;
+
+    for (i, direction) in directions.iter().enumerate() {
+        if i > 0 && directions[i - 1] != *direction {
+            run_start = i;
+        }
+
+        let best = match direction {
+            Some(Ordering::Greater) => &mut best_bull,
+            Some(Ordering::Less) => &mut best_bear,
+            _ => continue,
+        };
+        let length = i + 1 - run_start;
+        if length > best.length {
+            *best = Streak {
+                start_date: daily[run_start].date.clone(),
+                end_date: daily[i].date.clone(),
+                length,
+            };
+        }
+    }
+
+    (best_bull, best_bear)
+}
+
+/// Mean open/high/low/close of the candles labelled `pattern`, each placed in its own
+/// range (0.0 = low, 1.0 = high), so high and low are always 1.0 and 0.0 and the shape
+/// is carried by open and close. `None` if no candle with a non-zero range matches.
+pub fn mean_candle_shape(aggs: &[PeriodAgg], pattern: &str) -> Option<(f64, f64, f64, f64)> {
+    let (mut open_sum, mut close_sum, mut count) = (0.0, 0.0, 0usize);
+
+    for agg in aggs.iter().filter(|a| a.pattern == pattern) {
+        let range = agg.high - agg.low;
+        if range <= 0.0 { continue; }
+        open_sum += (agg.open - agg.low) / range;
+        close_sum += (agg.close - agg.low) / range;
+        count += 1;
+    }
+
+    if count == 0 {
+        return None;
+    }
+    let n = count as f64;
+    Some((open_sum / n, 1.0, 0.0, close_sum / n))
+}
@@ -1,17 +1,14 @@
-use std::cmp::Ordering;
 use std::collections::HashMap;
-use chrono::{Datelike, NaiveDate, NaiveDateTime, Weekday};
-use std::error::Error;
-use std::path::Path;
+use chrono::{Datelike, Weekday};
 use serde::{Deserialize, Serialize};
 
-use crate::data_engine::{CsvRecord, MarketData, parse_ts_to_naive};
-use crate::candle_type::{pattern_from_ohlc, CandlePattern, DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG, DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS};
+use crate::data_engine::{CsvRecord, iso_week_key, parse_ts_to_naive};
+use crate::candle_type::{pattern_from_ohlc, DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG, DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS};
 use crate::week_day_data::PeriodAgg;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WeeklyTableAgg {
-    pub year: String,
+    pub year: String, // ISO year of `week`, which can differ from the calendar year around New Year
     pub month: String,
     pub week: String,
     pub monday_pattern: String,
@@ -67,15 +64,14 @@ pub fn aggregate_weekly_table(daily_aggs: &[PeriodAgg]) -> Vec<WeeklyTableAgg> {
             Some(dt) => dt,
             None => continue,
         };
-        let week_key = format!("{}{}", ndt.iso_week().year(), ndt.iso_week().week());
-        weekly_map.entry(week_key)
-            .or_insert_with(Vec::new)
+        weekly_map.entry(iso_week_key(&ndt))
+            .or_default()
             .push(d_agg);
     }
 
     let mut result: Vec<WeeklyTableAgg> = Vec::new();
 
-    for (_key, daily_days) in weekly_map {
+    for daily_days in weekly_map.into_values() {
         if daily_days.is_empty() { continue; }
 
         let mut daily_days_sorted = daily_days;
@@ -120,7 +116,7 @@ pub fn aggregate_weekly_table(daily_aggs: &[PeriodAgg]) -> Vec<WeeklyTableAgg> {
         let first_day_ndt = parse_ts_to_naive(&first_day.date).unwrap();
 
         let weekly_agg = WeeklyTableAgg {
-            year: first_day_ndt.year().to_string(),
+            year: first_day_ndt.iso_week().year().to_string(),
             month: format!("{:02}", first_day_ndt.month()),
             week: format!("Week {}", first_day_ndt.iso_week().week()),
             monday_pattern: daily_patterns.get(&Weekday::Mon).cloned().unwrap_or_default(),
@@ -1,17 +1,15 @@
-use std::cmp::Ordering;
 use std::collections::HashMap;
-use chrono::{Datelike, NaiveDate, NaiveDateTime, Weekday};
-use std::error::Error;
-use std::path::Path;
+use chrono::{Datelike, NaiveDateTime, Weekday};
 use serde::{Deserialize, Serialize};
 
-use crate::data_engine::{CsvRecord, MarketData, parse_ts_to_naive};
-use crate::candle_type::{pattern_from_ohlc, CandlePattern, DEFAULT_DOJI_BODY_RATIO, DEFAULT_BODY_WICK_RATIO_LONG, DEFAULT_BODY_WICK_RATIO_SHORT, DEFAULT_UPPER_VS_LOWER_RATIO, DEFAULT_EPS};
+use crate::data_engine::{AggregateOptions, CsvRecord, FloatFormat, SortOrder, parse_ts_to_naive, iso_week_key};
+use crate::candle_type::{pattern_from_ohlc_cfg, CandlePattern};
 use crate::week_day_data::PeriodAgg;
+use crate::bar_relation::BarRelation;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WeeklyTableAgg {
-    pub year: String,
+    pub year: String, // ISO year of `week`, which can differ from the calendar year around New Year
     pub month: String,
     pub week: String,
     pub monday_pattern: String,
@@ -27,6 +25,10 @@ pub struct WeeklyTableAgg {
     pub high_day: String,
     pub low_day: String,
     pub week_pattern: String,
+    pub bar_relation: String, // Relation to the prior week; empty for the first week
+    pub pattern_sequence: String, // Monday..Friday short codes, see `pattern_sequence`
+    #[serde(default)]
+    pub start_date: String, // First trading day of the week (YYYY-MM-DD); not a CSV column
 }
 
 impl CsvRecord for WeeklyTableAgg {
@@ -34,10 +36,15 @@ impl CsvRecord for WeeklyTableAgg {
         &[
             "Year", "Month", "Week", "Monday", "Tuesday", "Wednesday", "Thursday",
             "Friday", "Open", "High", "Low", "Close", "Volume", "HighDay", "LowDay", "WeekPattern",
+            "BarRelation", "PatternSequence",
         ]
     }
 
     fn record(&self) -> Vec<String> {
+        self.record_fmt(FloatFormat::default())
+    }
+
+    fn record_fmt(&self, fmt: FloatFormat) -> Vec<String> {
         vec![
             self.year.clone(),
             self.month.clone(),
@@ -47,19 +54,34 @@ impl CsvRecord for WeeklyTableAgg {
             self.wednesday_pattern.clone(),
             self.thursday_pattern.clone(),
             self.friday_pattern.clone(),
-            format!("{:.6}", self.open),
-            format!("{:.6}", self.high),
-            format!("{:.6}", self.low),
-            format!("{:.6}", self.close),
-            format!("{:.6}", self.volume),
+            fmt.format(self.open),
+            fmt.format(self.high),
+            fmt.format(self.low),
+            fmt.format(self.close),
+            fmt.format(self.volume),
             self.high_day.clone(),
             self.low_day.clone(),
             self.week_pattern.clone(),
+            self.bar_relation.clone(),
+            self.pattern_sequence.clone(),
         ]
     }
 }
 
 pub fn aggregate_weekly_table(daily_aggs: &[PeriodAgg]) -> Vec<WeeklyTableAgg> {
+    aggregate_weekly_table_sorted(daily_aggs, SortOrder::Ascending)
+}
+
+/// `aggregate_weekly_table` with the output in `order`. `bar_relation` is always
+/// relative to the chronologically previous week, so weeks are built in date order
+/// and `SortOrder::Unsorted` leaves them that way.
+pub fn aggregate_weekly_table_sorted(daily_aggs: &[PeriodAgg], order: SortOrder) -> Vec<WeeklyTableAgg> {
+    aggregate_weekly_table_with(daily_aggs, &AggregateOptions { sort_order: order, ..AggregateOptions::default() })
+}
+
+/// `aggregate_weekly_table` with week patterns classified by `opts.candle_config`
+/// and the output in `opts.sort_order`.
+pub fn aggregate_weekly_table_with(daily_aggs: &[PeriodAgg], opts: &AggregateOptions) -> Vec<WeeklyTableAgg> {
     let mut weekly_map: HashMap<String, Vec<&PeriodAgg>> = HashMap::new();
     
     for d_agg in daily_aggs {
@@ -67,15 +89,15 @@ pub fn aggregate_weekly_table(daily_aggs: &[PeriodAgg]) -> Vec<WeeklyTableAgg> {
             Some(dt) => dt,
             None => continue,
         };
-        let week_key = format!("{}{}", ndt.iso_week().year(), ndt.iso_week().week());
+        let week_key = iso_week_key(&ndt);
         weekly_map.entry(week_key)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(d_agg);
     }
 
-    let mut result: Vec<WeeklyTableAgg> = Vec::new();
+    let mut result: Vec<(NaiveDateTime, WeeklyTableAgg)> = Vec::new();
 
-    for (_key, daily_days) in weekly_map {
+    for daily_days in weekly_map.into_values() {
         if daily_days.is_empty() { continue; }
 
         let mut daily_days_sorted = daily_days;
@@ -107,27 +129,26 @@ pub fn aggregate_weekly_table(daily_aggs: &[PeriodAgg]) -> Vec<WeeklyTableAgg> {
             daily_patterns.insert(ndt.weekday(), day.pattern.clone());
         }
         
-        let week_pattern = pattern_from_ohlc(
-            open, high, low, close,
-            DEFAULT_DOJI_BODY_RATIO,
-            DEFAULT_BODY_WICK_RATIO_LONG,
-            DEFAULT_BODY_WICK_RATIO_SHORT,
-            DEFAULT_UPPER_VS_LOWER_RATIO,
-            DEFAULT_EPS,
-        );
+        let week_pattern = pattern_from_ohlc_cfg(open, high, low, close, &opts.candle_config);
 
         let first_day = daily_days_sorted.first().unwrap();
         let first_day_ndt = parse_ts_to_naive(&first_day.date).unwrap();
 
+        let day_patterns = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri]
+            .map(|d| daily_patterns.get(&d).cloned().unwrap_or_default());
+        let sequence = pattern_sequence(&day_patterns);
+        let [monday_pattern, tuesday_pattern, wednesday_pattern, thursday_pattern, friday_pattern] = day_patterns;
+
         let weekly_agg = WeeklyTableAgg {
-            year: first_day_ndt.year().to_string(),
+            year: first_day_ndt.iso_week().year().to_string(),
             month: format!("{:02}", first_day_ndt.month()),
             week: format!("Week {}", first_day_ndt.iso_week().week()),
-            monday_pattern: daily_patterns.get(&Weekday::Mon).cloned().unwrap_or_default(),
-            tuesday_pattern: daily_patterns.get(&Weekday::Tue).cloned().unwrap_or_default(),
-            wednesday_pattern: daily_patterns.get(&Weekday::Wed).cloned().unwrap_or_default(),
-            thursday_pattern: daily_patterns.get(&Weekday::Thu).cloned().unwrap_or_default(),
-            friday_pattern: daily_patterns.get(&Weekday::Fri).cloned().unwrap_or_default(),
+            start_date: first_day_ndt.format("%Y-%m-%d").to_string(),
+            monday_pattern,
+            tuesday_pattern,
+            wednesday_pattern,
+            thursday_pattern,
+            friday_pattern,
             open,
             high,
             low,
@@ -136,11 +157,90 @@ pub fn aggregate_weekly_table(daily_aggs: &[PeriodAgg]) -> Vec<WeeklyTableAgg> {
             high_day: high_day.to_string(),
             low_day: low_day.to_string(),
             week_pattern,
+            bar_relation: String::new(),
+            pattern_sequence: sequence,
         };
-        result.push(weekly_agg);
+        result.push((first_day_ndt, weekly_agg));
     }
-    
-    result.sort_by(|a, b| a.year.cmp(&b.year).then_with(|| a.week.cmp(&b.week)));
 
-    result
-}
\ No newline at end of file
+    // Sort on the week's first date; the "Week N" strings don't order numerically.
+    result.sort_by_key(|(first_day, _)| *first_day);
+    for i in 1..result.len() {
+        let (prev, cur) = (&result[i - 1].1, &result[i].1);
+        result[i].1.bar_relation = BarRelation::from_ranges(prev.high, prev.low, cur.high, cur.low).to_string();
+    }
+
+    opts.sort_order.apply(&mut result, |a, b| a.0.cmp(&b.0));
+    result.into_iter().map(|(_, agg)| agg).collect()
+}
+
+/// Comma-joined `CandlePattern::short_code`s of `day_patterns` (labels as the day
+/// columns hold them), e.g. `"MB,DJ,BLB,-,RH"`. A missing day is `-`, an
+/// unrecognized label `?`.
+pub fn pattern_sequence(day_patterns: &[String]) -> String {
+    day_patterns.iter()
+        .map(|label| match label.as_str() {
+            "" => "-",
+            label => CandlePattern::from_label(label).map_or("?", |p| p.short_code()),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Inside/Outside/Up/Down relation of each week to the week before it.
+/// `weeks` must be in chronological order (as `aggregate_weekly_table` returns them);
+/// the result has one entry per consecutive pair, so `result[i]` describes `weeks[i + 1]`.
+pub fn weekly_bar_relationship(weeks: &[WeeklyTableAgg]) -> Vec<BarRelation> {
+    weeks.windows(2)
+        .map(|pair| BarRelation::from_ranges(pair[0].high, pair[0].low, pair[1].high, pair[1].low))
+        .collect()
+}
+
+/// Percentile rank of each week's `high - low` among the weeks before it (expanding
+/// window, so no lookahead), labelled `"<Year> <Week>"`. Ties count half. The first
+/// week has no history and gets 0.5. `weeks` must be in chronological order.
+pub fn weekly_range_percentile(weeks: &[WeeklyTableAgg]) -> Vec<(String, f64)> {
+    let ranges: Vec<f64> = weeks.iter().map(|w| w.high - w.low).collect();
+
+    weeks.iter().enumerate().map(|(i, week)| {
+        let label = format!("{} {}", week.year, week.week);
+        if i == 0 {
+            return (label, 0.5);
+        }
+        let prior = &ranges[..i];
+        let below = prior.iter().filter(|r| **r < ranges[i]).count() as f64;
+        let equal = prior.iter().filter(|r| **r == ranges[i]).count() as f64;
+        (label, (below + 0.5 * equal) / i as f64)
+    }).collect()
+}
+
+/// Count of `high_day` values for each `monday_pattern`. Weeks without a Monday are skipped.
+pub fn weekly_high_day_given_monday(weeks: &[WeeklyTableAgg]) -> HashMap<String, HashMap<String, usize>> {
+    let mut table: HashMap<String, HashMap<String, usize>> = HashMap::new();
+    for week in weeks.iter().filter(|w| !w.monday_pattern.is_empty()) {
+        *table.entry(week.monday_pattern.clone())
+            .or_default()
+            .entry(week.high_day.clone())
+            .or_insert(0) += 1;
+    }
+    table
+}
+
+/// Average `(close - open) / open` and count per week-of-month, where week N holds
+/// weeks whose first trading day falls on day `7(N-1)+1 ..= 7N` of its month.
+pub fn returns_by_week_of_month(weeks: &[WeeklyTableAgg]) -> HashMap<u32, (f64, usize)> {
+    let mut sums: HashMap<u32, (f64, usize)> = HashMap::new();
+    for week in weeks {
+        if week.open == 0.0 { continue; }
+        let first = match parse_ts_to_naive(&week.start_date) {
+            Some(dt) => dt,
+            None => continue,
+        };
+        let entry = sums.entry((first.day() - 1) / 7 + 1).or_insert((0.0, 0));
+        entry.0 += (week.close - week.open) / week.open;
+        entry.1 += 1;
+    }
+    sums.into_iter()
+        .map(|(n, (total, count))| (n, (total / count as f64, count)))
+        .collect()
+}